@@ -1,35 +1,37 @@
-use super::{get_rotation_mat, get_scale_mat, get_size_after_affine_trans, get_translate_mat};
-use crate::util::{OptionExt as _, Result};
+use super::{get_dest_size, get_inv_trans_mat};
+use crate::{OptionExt as _, Result};
 use ndarray::prelude::*;
 
 const OPENCL_KERNEL_SRC: &str = include_str!("./kernel.cl");
 
-pub fn affine_trans(src: Array3<f32>, scale: f32, rotate: f32) -> Result<Array3<f32>> {
+pub fn affine_trans(
+    src: Array3<f32>,
+    scale: f32,
+    rotate: f32,
+    shift: (f32, f32),
+    keep_size: bool,
+) -> Result<Array3<f32>> {
     use ocl::{
         enums::{ImageChannelDataType, ImageChannelOrder, MemObjectType},
         prm, Context, Device, Image, Kernel, Program, Queue,
     };
 
-    let (h, w, _) = src.dim();
-    let (h2, w2) = get_size_after_affine_trans(h, w, scale, rotate);
+    let (h, w, ncol) = src.dim();
+    let (h2, w2) = get_dest_size(h, w, scale, rotate, keep_size);
 
     // Most OpenCL implementations support only f32-RGBA image,
     // so we need to expand it first.
     let src_rgba_buf = Array::from_shape_fn((h, w, 4), |(x, y, col)| {
-        if col != 3 {
+        if col < ncol {
             src[[x, y, col]]
         } else {
-            // Alpha
+            // Padding, dropped after processing.
             1.0
         }
     })
     .into_raw_vec();
 
-    // Inverse matrix. So we can get source points for each destination points.
-    let inv_trans_mat = get_translate_mat(h as f32 / 2.0, w as f32 / 2.0)
-        .dot(&get_rotation_mat(-rotate))
-        .dot(&get_scale_mat(1.0 / scale))
-        .dot(&get_translate_mat(-(h2 as f32 / 2.0), -(w2 as f32 / 2.0)));
+    let inv_trans_mat = get_inv_trans_mat((h, w), (h2, w2), scale, rotate, shift);
     let mat_flatten = prm::Float8::from([
         inv_trans_mat[[0, 0]],
         inv_trans_mat[[0, 1]],
@@ -89,7 +91,7 @@ pub fn affine_trans(src: Array3<f32>, scale: f32, rotate: f32) -> Result<Array3<
     // RGBA output
     let mut buf = vec![0.0f32; h2 * w2 * 4];
     dest_image.read(&mut buf[..]).enq()?;
-    // Convert back to RGB
+    // Convert back to the original channels
     let dest_rgba = Array::from_shape_vec((h2, w2, 4), buf).unwrap();
-    Ok(dest_rgba.slice(s![.., .., ..3]).to_owned())
+    Ok(dest_rgba.slice(s![.., .., ..ncol]).to_owned())
 }
@@ -0,0 +1,238 @@
+use crate::{
+    image::Image,
+    processor::{Job, ParamKind, ParamSpec, Params, Processor},
+    Result,
+};
+use ndarray::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub mod cl;
+
+pub struct AffineTransform;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AffineParams {
+    pub scale: f32,
+    /// Rotation in degrees.
+    pub rotate: f32,
+    /// Translation in pixels, applied after scaling and rotation.
+    pub shift_x: f32,
+    pub shift_y: f32,
+    /// Keep the input size, cropping the transformed image and filling uncovered pixels with 0.
+    pub keep_size: bool,
+    pub ocl: bool,
+}
+
+impl Params for AffineParams {
+    const SPECS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "scale",
+            desc: "Scale factor",
+            kind: ParamKind::Float {
+                min: 0.01,
+                max: 100.0,
+                default: 1.0,
+            },
+        },
+        ParamSpec {
+            name: "rotate",
+            desc: "Rotation in degrees",
+            kind: ParamKind::Float {
+                min: -360.0,
+                max: 360.0,
+                default: 0.0,
+            },
+        },
+        ParamSpec {
+            name: "shift_x",
+            desc: "Vertical translation in pixels",
+            kind: ParamKind::Float {
+                min: -10000.0,
+                max: 10000.0,
+                default: 0.0,
+            },
+        },
+        ParamSpec {
+            name: "shift_y",
+            desc: "Horizontal translation in pixels",
+            kind: ParamKind::Float {
+                min: -10000.0,
+                max: 10000.0,
+                default: 0.0,
+            },
+        },
+        ParamSpec {
+            name: "keep_size",
+            desc: "Keep the input size instead of enlarging to contain the whole image",
+            kind: ParamKind::Bool { default: false },
+        },
+        ParamSpec {
+            name: "ocl",
+            desc: "Run with OpenCL",
+            kind: ParamKind::Bool { default: false },
+        },
+    ];
+}
+
+impl Processor for AffineTransform {
+    const NAME: &'static str = "affine";
+    type Params = AffineParams;
+
+    fn run(&self, params: &AffineParams, src: Image, job: &Job) -> Result<Image> {
+        let rotate = params.rotate.to_radians();
+        let shift = (params.shift_x, params.shift_y);
+        let src = src.expect_normal()?;
+        let dest = if params.ocl {
+            cl::affine_trans(src, params.scale, rotate, shift, params.keep_size)?
+        } else {
+            affine_trans(src, params.scale, rotate, shift, params.keep_size, job)?
+        };
+        Ok(Image::Normal(dest))
+    }
+}
+
+/// Scale and rotate (in radians) the image around its center, then translate it by `shift`.
+/// Output is enlarged to contain the whole transformed image, regardless of the translation,
+/// unless `keep_size`. Pixels not covered by the image are 0.
+pub fn affine_trans(
+    src: Array3<f32>,
+    scale: f32,
+    rotate: f32,
+    shift: (f32, f32),
+    keep_size: bool,
+    job: &Job,
+) -> Result<Array3<f32>> {
+    let (h, w, ncol) = src.dim();
+    let (h2, w2) = get_dest_size(h, w, scale, rotate, keep_size);
+    let inv_trans_mat = get_inv_trans_mat((h, w), (h2, w2), scale, rotate, shift);
+
+    let progress = job.progress(h2);
+    let mut dest = Array::zeros((h2, w2, ncol));
+    ndarray::Zip::indexed(&mut dest).par_apply(|(dest_x, dest_y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if dest_y == 0 && col == 0 {
+            progress.tick();
+        }
+        // Slow: Result matrix is on heap.
+        // let src_pt = inv_trans_mat.dot(&array![[dest_x as f32], [dest_y as f32], [1.]]);
+        // let (x, y) = (src_pt[[0, 0]], src_pt[[1, 0]]);
+
+        let (dest_x, dest_y) = (dest_x as f32, dest_y as f32);
+        let m = &inv_trans_mat;
+        let x = m[[0, 0]] * dest_x + m[[0, 1]] * dest_y + m[[0, 2]];
+        let y = m[[1, 0]] * dest_x + m[[1, 1]] * dest_y + m[[1, 2]];
+
+        // Top-left corner of neighborhood.
+        let (x_, y_) = (x.floor() as isize - 1, y.floor() as isize - 1);
+
+        let mut xsamples = [0.0; 4];
+        for i in 0..4 {
+            let mut ysamples = [0.0; 4];
+            for j in 0..4 {
+                // Treat neighbor pixels out of image as black.
+                if 0 <= x_ + i && x_ + i < h as _ && 0 <= y_ + j && y_ + j < w as _ {
+                    ysamples[j as usize] = src[[(x_ + i) as _, (y_ + j) as _, col]];
+                }
+            }
+            xsamples[i as usize] = interpolate3(ysamples, y - y_ as f32);
+        }
+        *v = interpolate3(xsamples, x - x_ as f32);
+    });
+
+    job.check()?;
+    Ok(dest)
+}
+
+/// Inverse matrix. So we can get source points for each destination points.
+fn get_inv_trans_mat(
+    (h, w): (usize, usize),
+    (h2, w2): (usize, usize),
+    scale: f32,
+    rotate: f32,
+    (shift_x, shift_y): (f32, f32),
+) -> Array2<f32> {
+    get_translate_mat(h as f32 / 2.0, w as f32 / 2.0)
+        .dot(&get_rotation_mat(-rotate))
+        .dot(&get_scale_mat(1.0 / scale))
+        .dot(&get_translate_mat(
+            -(h2 as f32 / 2.0 + shift_x),
+            -(w2 as f32 / 2.0 + shift_y),
+        ))
+}
+
+fn get_dest_size(h: usize, w: usize, scale: f32, rotate: f32, keep_size: bool) -> (usize, usize) {
+    if keep_size {
+        (h, w)
+    } else {
+        get_size_after_affine_trans(h, w, scale, rotate)
+    }
+}
+
+fn get_size_after_affine_trans(h: usize, w: usize, scale: f32, rotate: f32) -> (usize, usize) {
+    let rot_mat = get_rotation_mat(rotate).dot(&get_scale_mat(scale));
+    // Transform two border points to locate the result rectangle.
+    let p1 = rot_mat.dot(&array![[h as f32], [w as f32], [1.]]);
+    let p2 = rot_mat.dot(&array![[h as f32], [-(w as f32)], [1.]]);
+    let h2 = p1[[0, 0]].abs().max(p2[[0, 0]].abs());
+    let w2 = p1[[1, 0]].abs().max(p2[[1, 0]].abs());
+    (h2.round() as usize, w2.round() as usize)
+}
+
+#[rustfmt::skip]
+fn get_rotation_mat(th: f32) -> Array2<f32> {
+    let (s, c) = (th.sin(), th.cos());
+    array![
+        [ c, -s , 0.],
+        [ s,  c , 0.],
+        [ 0., 0., 1.],
+    ]
+}
+
+#[rustfmt::skip]
+fn get_translate_mat(x: f32, y: f32) -> Array2<f32> {
+    array![
+        [ 1., 0., x ],
+        [ 0., 1., y ],
+        [ 0., 0., 1.],
+    ]
+}
+
+#[rustfmt::skip]
+fn get_scale_mat(scale: f32) -> Array2<f32> {
+    array![
+        [ scale,    0., 0.],
+        [    0., scale, 0.],
+        [    0.,    0., 1.],
+    ]
+}
+
+#[rustfmt::skip]
+fn interpolate3(y: [f32; 4], tx: f32) -> f32 {
+    let x = [0.0, 1.0, 2.0, 3.0];
+      (tx - x[1]) * (tx - x[2]) * (tx - x[3]) / ((x[0] - x[1]) * (x[0] - x[2]) * (x[0] - x[3])) * y[0]
+    + (tx - x[0]) * (tx - x[2]) * (tx - x[3]) / ((x[1] - x[0]) * (x[1] - x[2]) * (x[1] - x[3])) * y[1]
+    + (tx - x[0]) * (tx - x[1]) * (tx - x[3]) / ((x[2] - x[0]) * (x[2] - x[1]) * (x[2] - x[3])) * y[2]
+    + (tx - x[0]) * (tx - x[1]) * (tx - x[2]) / ((x[3] - x[0]) * (x[3] - x[1]) * (x[3] - x[2])) * y[3]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation_mat() {
+        let pt1 = array![[3.0f32.sqrt()], [1.0], [1.0]];
+        let pt2 = array![[0.0], [2.0], [1.0]];
+        let dt = get_rotation_mat(std::f32::consts::PI / 3.0).dot(&pt1) - pt2;
+        assert!(dt.iter().all(|x| x.abs() < 1e-5));
+    }
+
+    #[test]
+    fn test_interpolate3() {
+        assert!((interpolate3([0.0, 1.0, 2.0, 3.0], 1.5) - 1.5).abs() < 1e-5);
+        assert!((interpolate3([0.0, 1.0, 4.0, 9.0], -2.0) - 4.0).abs() < 1e-5);
+        assert!((interpolate3([0.0, 1.0, 8.0, 27.0], -4.0) - -64.0).abs() < 1e-5);
+    }
+}
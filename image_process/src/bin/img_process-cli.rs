@@ -0,0 +1,239 @@
+use failure::{ensure, format_err, ResultExt as _};
+use serde_json::{Map, Value};
+use std::{fs, path::Path, time::Instant};
+use vision_works::{
+    affine::AffineTransform,
+    image::Image,
+    pipeline::Recipe,
+    processor::{
+        find_processor, format_duration, load_processors, ImageProcessor, Job, ParamKind,
+        Params as _,
+    },
+    register::register,
+    Result,
+};
+
+const USAGE: &str = "\
+Usage: img_process-cli <PROCESSOR> [CHOICE]... [--<PARAM> <VALUE>]... <INPUT> <OUTPUT>
+       img_process-cli recipe <RECIPE> [--format <EXT>] <INPUT> <OUTPUT>
+       img_process-cli register [--rotation-scale] <REFERENCE> <MOVING> <OUTPUT>
+
+Positional CHOICEs set choice parameters in order, eg. `filter bilateral`.
+Boolean parameters are flags without a value, eg. `affine --ocl`.
+Dashes in parameter names can be used in place of underlines.
+
+`recipe` applies steps exported from img_process. If INPUT is a directory,
+every image in it is processed and saved into the OUTPUT directory with the same
+file name, or with extension EXT if `--format` is given.
+
+`register` estimates the translation, and optionally rotation and scale, of
+MOVING relative to REFERENCE by phase correlation, and saves MOVING aligned by
+`affine` with the estimated parameters, in the size of REFERENCE.
+";
+
+macro_rules! log {
+    ($($tt:tt)*) => {
+        eprintln!($($tt)*)
+    };
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() || args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        print_help();
+        return;
+    }
+    if let Err(err) = run(args) {
+        let causes: Vec<String> = err.iter_chain().map(|c| c.to_string()).collect();
+        log!("Error: {}", causes.join(": "));
+        std::process::exit(1);
+    }
+}
+
+/// Job with messages from processors logged.
+fn new_job() -> Job {
+    Job::default().with_log(|msg| log!("{}", msg))
+}
+
+fn print_help() {
+    print!("{}", USAGE);
+    println!("\nProcessors:");
+    for pro in load_processors() {
+        println!("    {}", pro.name());
+        for spec in pro.specs() {
+            println!("        {}", spec);
+        }
+    }
+}
+
+/// Parse `[CHOICE]... [--<PARAM> <VALUE>]... <INPUT> <OUTPUT>` according to the parameter specs.
+fn parse_args(pro: &dyn ImageProcessor, args: Vec<String>) -> Result<(Value, Vec<String>)> {
+    let specs = pro.specs();
+    let mut params = Map::new();
+    let mut positional = Vec::new();
+
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        if !arg.starts_with("--") {
+            positional.push(arg);
+            continue;
+        }
+        let name = arg[2..].replace('-', "_");
+        let spec = specs
+            .iter()
+            .find(|spec| spec.name == name)
+            .ok_or_else(|| format_err!("Unknown parameter `{}`", arg))?;
+        let value = match spec.kind {
+            ParamKind::Bool { .. } => Value::Bool(true),
+            _ => {
+                let s = iter
+                    .next()
+                    .ok_or_else(|| format_err!("Missing value for `{}`", arg))?;
+                spec.parse(&s)
+                    .map_err(|err| format_err!("Invalid value of `{}`: {}", arg, err))?
+            }
+        };
+        ensure!(
+            params.insert(spec.name.to_owned(), value).is_none(),
+            "Duplicated parameter `{}`",
+            arg,
+        );
+    }
+
+    // The last two are input and output files.
+    ensure!(positional.len() >= 2, "Missing input or output file");
+    let files = positional.split_off(positional.len() - 2);
+    let mut choices = specs
+        .iter()
+        .filter(|spec| matches!(spec.kind, ParamKind::Choice { .. }));
+    for s in positional {
+        let spec = choices
+            .next()
+            .ok_or_else(|| format_err!("Unexpected argument `{}`", s))?;
+        let value = spec
+            .parse(&s)
+            .map_err(|err| format_err!("Invalid {}: {}", spec.name, err))?;
+        ensure!(
+            params.insert(spec.name.to_owned(), value).is_none(),
+            "Duplicated parameter `{}`",
+            spec.name,
+        );
+    }
+
+    Ok((Value::Object(params), files))
+}
+
+fn run(mut args: Vec<String>) -> Result<()> {
+    if args[0] == "recipe" {
+        return run_recipe(args.split_off(1));
+    }
+    if args[0] == "register" {
+        return run_register(args.split_off(1));
+    }
+
+    let pro = find_processor(&args.remove(0))?;
+    let (params, files) = parse_args(&*pro, args)?;
+    // Report invalid parameters before loading the image.
+    let params = pro.check_args(&params)?;
+    let (input, output) = (&files[0], &files[1]);
+
+    log!("Loading file {}", input);
+    let img = Image::open(Path::new(input)).context("Load image")?;
+
+    log!("Running processor {} with {}", pro.name(), params);
+    let t = Instant::now();
+    let ret = pro.run(&params, img, &new_job())?;
+    log!("Done in {}", format_duration(t.elapsed()));
+
+    ret.save(Path::new(output)).context("Save image")?;
+    log!("Saved to {}", output);
+    Ok(())
+}
+
+fn run_recipe(mut args: Vec<String>) -> Result<()> {
+    let mut format = None;
+    if let Some(pos) = args.iter().position(|arg| arg == "--format") {
+        ensure!(pos + 1 < args.len(), "Missing value for `--format`");
+        format = Some(args.remove(pos + 1));
+        args.remove(pos);
+    }
+    ensure!(args.len() == 3, "Expecting <RECIPE> <INPUT> <OUTPUT>");
+    let (input, output) = (Path::new(&args[1]), Path::new(&args[2]));
+
+    let recipe = Recipe::load(Path::new(&args[0])).context("Load recipe")?;
+    log!("Loaded recipe of {} steps", recipe.steps.len());
+
+    if !input.is_dir() {
+        return run_recipe_file(&recipe, input, output);
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(input)? {
+        let path = entry?.path();
+        if path.is_file() && ::image::ImageFormat::from_path(&path).is_ok() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    fs::create_dir_all(output)?;
+
+    let t = Instant::now();
+    let mut failed = 0;
+    for (i, path) in files.iter().enumerate() {
+        let mut dest = output.join(path.file_name().unwrap());
+        if let Some(ext) = &format {
+            dest.set_extension(ext);
+        }
+        log!("[{}/{}] {}", i + 1, files.len(), path.display());
+        if let Err(err) = run_recipe_file(&recipe, path, &dest) {
+            let causes: Vec<String> = err.iter_chain().map(|c| c.to_string()).collect();
+            log!("Error: {}", causes.join(": "));
+            failed += 1;
+        }
+    }
+    log!(
+        "Processed {} images in {}",
+        files.len() - failed,
+        format_duration(t.elapsed()),
+    );
+    ensure!(failed == 0, "{} of {} images failed", failed, files.len());
+    Ok(())
+}
+
+fn run_register(mut args: Vec<String>) -> Result<()> {
+    let rotation_scale = match args.iter().position(|arg| arg == "--rotation-scale") {
+        Some(pos) => {
+            args.remove(pos);
+            true
+        }
+        None => false,
+    };
+    ensure!(args.len() == 3, "Expecting <REFERENCE> <MOVING> <OUTPUT>");
+
+    let reference = Image::open(Path::new(&args[0]))
+        .context("Load reference")?
+        .expect_normal()?;
+    let moving = Image::open(Path::new(&args[1]))
+        .context("Load image")?
+        .expect_normal()?;
+
+    let t = Instant::now();
+    let job = new_job();
+    let reg = register(&reference, &moving, rotation_scale, &job)?;
+    log!("Estimated {:?} in {}", reg, format_duration(t.elapsed()));
+
+    let params = reg.to_affine_params().to_args();
+    log!("Running processor affine with {}", params);
+    let ret = AffineTransform.run(&params, Image::Normal(moving), &job)?;
+    ret.save(Path::new(&args[2])).context("Save image")?;
+    log!("Saved to {}", args[2]);
+    Ok(())
+}
+
+fn run_recipe_file(recipe: &Recipe, input: &Path, output: &Path) -> Result<()> {
+    let img = Image::open(input).context("Load image")?;
+    let ret = recipe.run(img, &new_job())?;
+    ret.save(output).context("Save image")?;
+    log!("Saved to {}", output.display());
+    Ok(())
+}
@@ -4,7 +4,7 @@ use gio::prelude::*;
 use glib::value::Value;
 use gtk::{prelude::*, Application, ApplicationWindow, Builder};
 use once_cell::sync::OnceCell;
-use std::{cell::RefCell, rc::Rc, sync::Arc};
+use std::{cell::RefCell, path::PathBuf, rc::Rc, sync::Arc};
 
 macro_rules! log {
     ($fmt:literal $($tt:tt)*) => {
@@ -14,8 +14,16 @@ macro_rules! log {
 
 mod processor;
 mod util;
-use processor::{load_processors, ImageProcessor};
-use util::{BuilderExtManualExt as _, Image};
+use processor::{active_choice, load_processors, Widgets};
+use util::{BuilderExtManualExt as _, ImageExt as _};
+use vision_works::{
+    affine::AffineTransform,
+    filter::parse_matrix,
+    image::{Image, SpectrumStyle},
+    pipeline::{Pipeline, Step},
+    processor::{format_duration, is_cancelled, Args, ImageProcessor, Job, Params as _},
+    register::{register, Registration},
+};
 
 const GLADE_SRC_PATH: &str = "glade/img_process.glade";
 static GUI_EVENT_TX: OnceCell<glib::Sender<GuiEvent>> = OnceCell::new();
@@ -23,7 +31,12 @@ static GUI_EVENT_TX: OnceCell<glib::Sender<GuiEvent>> = OnceCell::new();
 #[derive(Debug)]
 enum GuiEvent {
     Log(String),
-    ImageOutput(Image),
+    /// The output and the pipeline producing it.
+    ImageOutput(Image, Pipeline),
+    /// Fraction of the running job done.
+    Progress(f32),
+    /// Estimated alignment of the current image, to be applied by `affine`.
+    Registered(Registration),
     WorkerError,
 }
 
@@ -31,8 +44,11 @@ enum GuiEvent {
 struct GuiState {
     image_input: Option<(Image, Pixbuf)>,
     image_output: Option<(Image, Pixbuf)>,
-    processing: bool,
+    pipeline: Pipeline,
+    /// The running job, if any.
+    job: Option<Job>,
     auto_shrink: bool,
+    spectrum_style: SpectrumStyle,
 }
 
 impl Default for GuiState {
@@ -40,8 +56,10 @@ impl Default for GuiState {
         Self {
             image_input: None,
             image_output: None,
-            processing: false,
+            pipeline: Pipeline::default(),
+            job: None,
             auto_shrink: true,
+            spectrum_style: SpectrumStyle::default(),
         }
     }
 }
@@ -87,9 +105,9 @@ fn on_gui_event(builder: &Builder, state: &Rc<RefCell<GuiState>>, event: GuiEven
             buf.insert_at_cursor(&content);
             txt.scroll_to_mark(&mark, 0.0, false, 0.0, 0.0);
         }
-        GuiEvent::ImageOutput(img) => {
-            let pixbuf = img.render();
+        GuiEvent::ImageOutput(img, pipeline) => {
             let mut st = state.borrow_mut();
+            let pixbuf = img.render(&st.spectrum_style);
             auto_rerender(
                 builder,
                 &st,
@@ -97,10 +115,53 @@ fn on_gui_event(builder: &Builder, state: &Rc<RefCell<GuiState>>, event: GuiEven
                 &pixbuf,
             );
             st.image_output = Some((img, pixbuf));
-            st.processing = false;
+            st.pipeline = pipeline;
+            st.job = None;
+            update_pipeline_view(builder, &st.pipeline);
+            builder
+                .object::<gtk::Button>("btn_cancel")
+                .set_sensitive(false);
+            builder
+                .object::<gtk::ProgressBar>("prg_job")
+                .set_fraction(1.0);
+        }
+        GuiEvent::Progress(frac) => {
+            // Late reports of a finished job are dropped.
+            if state.borrow().job.is_some() {
+                builder
+                    .object::<gtk::ProgressBar>("prg_job")
+                    .set_fraction(frac.into());
+            }
+        }
+        GuiEvent::Registered(reg) => {
+            state.borrow_mut().job = None;
+            builder
+                .object::<gtk::Button>("btn_cancel")
+                .set_sensitive(false);
+            let params = reg.to_affine_params();
+            for &(name, value) in &[
+                ("scl_affine_trans_scale", params.scale),
+                ("scl_affine_trans_rotate", params.rotate),
+                ("scl_affine_trans_shift_x", params.shift_x),
+                ("scl_affine_trans_shift_y", params.shift_y),
+            ] {
+                builder.object::<gtk::Scale>(name).set_value(value.into());
+            }
+            builder
+                .object::<gtk::ToggleButton>("chk_affine_trans_keep_size")
+                .set_active(params.keep_size);
+            processor_runner(builder, state, Arc::new(AffineTransform), params.to_args());
         }
         GuiEvent::WorkerError => {
-            state.borrow_mut().processing = false;
+            let mut st = state.borrow_mut();
+            st.job = None;
+            // Restore the output cleared when the job started.
+            if let Some((_, pixbuf)) = &st.image_output {
+                auto_rerender(builder, &st, &builder.object("img_output"), pixbuf);
+            }
+            builder
+                .object::<gtk::Button>("btn_cancel")
+                .set_sensitive(false);
         }
     }
 }
@@ -116,14 +177,14 @@ fn send_log_event(content: String) {
 fn resolve_handler(
     builder: &Builder,
     state: &Rc<RefCell<GuiState>>,
-    processors: &[Arc<dyn ImageProcessor>],
+    processors: &[(Arc<dyn ImageProcessor>, &'static dyn Widgets)],
     handler_name: &str,
 ) -> Box<dyn Fn(&[Value]) -> Option<Value> + 'static> {
     let builder = builder.clone();
     let state = state.clone();
     let state_ = state.clone();
     let check_processing = move || {
-        if state_.borrow().processing {
+        if state_.borrow().job.is_some() {
             log!("Error: Anothing job is running. Please wait.");
             true
         } else {
@@ -143,6 +204,28 @@ fn resolve_handler(
             }
             None
         }),
+        "on_cancel_job" => Box::new(move |_| {
+            match &state.borrow().job {
+                Some(job) => {
+                    job.cancel();
+                    log!("Cancelling...");
+                }
+                None => log!("Error: No running job"),
+            }
+            None
+        }),
+        "on_save_output" => Box::new(move |_| {
+            on_save_output(&builder, &state.borrow());
+            None
+        }),
+        "on_export_recipe" => Box::new(move |_| {
+            on_export_recipe(&builder, &state.borrow());
+            None
+        }),
+        "on_load_filter_kernel" => Box::new(move |_| {
+            on_load_filter_kernel(&builder);
+            None
+        }),
         "on_clear_log" => Box::new(move |_| {
             let txt_log: gtk::TextView = builder.object("txt_log");
             txt_log.get_buffer().unwrap().set_text("");
@@ -154,6 +237,9 @@ fn resolve_handler(
             }
             let st = &mut *state.borrow_mut();
             std::mem::swap(&mut st.image_input, &mut st.image_output);
+            // The output is baked into the new input.
+            st.pipeline = Pipeline::default();
+            update_pipeline_view(&builder, &st.pipeline);
             let img1: gtk::Image = builder.object("img_input");
             let img2: gtk::Image = builder.object("img_output");
             let (buf1, buf2) = (img1.get_pixbuf(), img2.get_pixbuf());
@@ -161,6 +247,24 @@ fn resolve_handler(
             img2.set_from_pixbuf(buf1.as_ref());
             None
         }),
+        "on_pipeline_undo" | "on_pipeline_redo" | "on_pipeline_remove" | "on_pipeline_clear"
+        | "on_pipeline_rerun" => {
+            let handler_name = handler_name.to_owned();
+            Box::new(move |_| {
+                if !check_processing() {
+                    on_pipeline_action(&builder, &state, &handler_name);
+                }
+                None
+            })
+        }
+        "on_pipeline_edit_args" => Box::new(move |values| {
+            if !check_processing() {
+                let path = values[1].get::<String>().ok().flatten().unwrap_or_default();
+                let text = values[2].get::<String>().ok().flatten().unwrap_or_default();
+                on_pipeline_edit_args(&builder, &state, &path, &text);
+            }
+            None
+        }),
         "on_wnd_resize" => Box::new(move |_| {
             on_resize(&builder, &state.borrow(), false);
             None
@@ -171,15 +275,25 @@ fn resolve_handler(
             on_resize(&builder, &st, true);
             None
         }),
+        "on_register_run" => Box::new(move |_| {
+            if !check_processing() {
+                on_register_run(&builder, &state);
+            }
+            None
+        }),
+        "on_spectrum_style_changed" => Box::new(move |_| {
+            on_spectrum_style_changed(&builder, &mut state.borrow_mut());
+            None
+        }),
         _ => {
-            for pro in processors {
+            for (pro, widgets) in processors {
                 let builder_ = builder.clone();
                 let pro_ = pro.clone();
                 let state_ = state.clone();
                 let run = Box::new(move |args| {
                     processor_runner(&builder_, &state_, pro_.clone(), args);
                 });
-                if let Some(h) = pro.register_handler(&builder, handler_name, run) {
+                if let Some(h) = widgets.register_handler(&builder, handler_name, run) {
                     return Box::new(move |_| {
                         h();
                         None
@@ -191,59 +305,89 @@ fn resolve_handler(
     }
 }
 
+/// Append a step to the pipeline and run it on the current output.
 fn processor_runner(
     builder: &Builder,
     state: &Rc<RefCell<GuiState>>,
     pro: Arc<dyn ImageProcessor>,
-    args: Box<dyn std::any::Any + Send>,
+    args: Args,
+) {
+    let mut pipeline = state.borrow().pipeline.clone();
+    match Step::new(pro.name(), &args) {
+        Ok(step) => pipeline.push(step),
+        Err(err) => {
+            log!("Error: {}", err);
+            return;
+        }
+    }
+    pipeline_runner(builder, state, pipeline, true);
+}
+
+/// Run `pipeline` in a worker thread. The output and the pipeline are updated together on success.
+/// If `incremental`, only the last step is run, on the current output.
+fn pipeline_runner(
+    builder: &Builder,
+    state: &Rc<RefCell<GuiState>>,
+    pipeline: Pipeline,
+    incremental: bool,
 ) {
     let mut st = state.borrow_mut();
-    if st.processing {
+    if st.job.is_some() {
         log!("Error: Anothing job is running. Please wait.");
         return;
     }
 
-    // Clear output buffer.
-    builder
-        .object::<gtk::Image>("img_output")
-        .set_from_pixbuf(None);
-
-    let img = match st.image_input.as_ref() {
-        Some((img, _)) => img.clone(),
+    // The current output is always produced by `st.pipeline`.
+    let continued = match &st.image_output {
+        Some((img, _)) if incremental && !st.pipeline.steps().is_empty() => Some(img.clone()),
+        _ => None,
+    };
+    let incremental = continued.is_some();
+    let img = match continued.or_else(|| st.image_input.as_ref().map(|(img, _)| img.clone())) {
+        Some(img) => img,
         None => {
             log!("Error: No input image");
             return;
         }
     };
 
-    st.processing = true;
-    log!("Running processor...");
+    // Clear output buffer.
+    builder
+        .object::<gtk::Image>("img_output")
+        .set_from_pixbuf(None);
+
+    let job = start_job(builder, &mut st);
+    if incremental {
+        log!("Running processor...");
+    } else {
+        log!("Running pipeline of {} steps...", pipeline.steps().len());
+    }
 
     let worker_handle = std::thread::spawn(move || {
         let t = std::time::Instant::now();
-        let ret = pro.run(args, img);
-        let ns = t.elapsed().as_nanos();
-        (ret, ns)
+        let ret = if incremental {
+            pipeline.steps().last().unwrap().run(img, &job)
+        } else {
+            pipeline.run(img, &job)
+        };
+        (ret.map(|img| (img, pipeline)), t.elapsed())
     });
 
     // Watching dog
     std::thread::spawn(move || {
         match worker_handle.join() {
-            Ok((Ok(ret_img), ns)) => {
+            Ok((Ok((ret_img, pipeline)), elapsed)) => {
                 GUI_EVENT_TX
                     .get()
                     .unwrap()
-                    .send(GuiEvent::ImageOutput(ret_img))
+                    .send(GuiEvent::ImageOutput(ret_img, pipeline))
                     .unwrap();
-                log!(
-                    "Done in {}.{:03} {:03} {:03} s",
-                    ns / 1_000_000_000,
-                    ns / 1_000_000 % 1_000,
-                    ns / 1_000 % 1_000,
-                    ns / 1 % 1_000,
-                );
+                log!("Done in {}", format_duration(elapsed));
                 return;
             }
+            Ok((Err(err), elapsed)) if is_cancelled(&err) => {
+                log!("Cancelled after {}", format_duration(elapsed));
+            }
             Ok((Err(err), _)) => log!("Error: {}", err),
             Err(err) => {
                 log!("Error: Worker panicked: {:?}", err);
@@ -257,6 +401,150 @@ fn processor_runner(
     });
 }
 
+/// Create a job with its progress shown, and allow to cancel it.
+fn start_job(builder: &Builder, st: &mut GuiState) -> Job {
+    let job = Job::with_progress(|frac| {
+        GUI_EVENT_TX
+            .get()
+            .unwrap()
+            .send(GuiEvent::Progress(frac))
+            .unwrap();
+    })
+    .with_log(|msg| log!("{}", msg));
+    st.job = Some(job.clone());
+    builder
+        .object::<gtk::Button>("btn_cancel")
+        .set_sensitive(true);
+    builder
+        .object::<gtk::ProgressBar>("prg_job")
+        .set_fraction(0.0);
+    job
+}
+
+/// Estimate the alignment of the current image to the reference file in a worker thread.
+/// The result is applied as an `affine` step.
+fn on_register_run(builder: &Builder, state: &Rc<RefCell<GuiState>>) {
+    let reference_path = match builder
+        .object::<gtk::FileChooser>("file_register_reference")
+        .get_filename()
+    {
+        Some(path) => path,
+        None => {
+            log!("Error: No reference image");
+            return;
+        }
+    };
+    let rotation_scale = builder
+        .object::<gtk::ToggleButton>("chk_register_rotation_scale")
+        .get_active();
+
+    let mut st = state.borrow_mut();
+    // The image which the next step runs on.
+    let moving = match (&st.image_output, &st.image_input) {
+        (Some((img, _)), _) if !st.pipeline.steps().is_empty() => img.clone(),
+        (_, Some((img, _))) => img.clone(),
+        _ => {
+            log!("Error: No input image");
+            return;
+        }
+    };
+    let job = start_job(builder, &mut st);
+    log!("Registering to {}...", reference_path.display());
+
+    let worker_handle = std::thread::spawn(move || -> vision_works::Result<Registration> {
+        let t = std::time::Instant::now();
+        let reference = Image::open(&reference_path).context("Load reference")?;
+        let reg = register(
+            &reference.expect_normal()?,
+            &moving.expect_normal()?,
+            rotation_scale,
+            &job,
+        )?;
+        log!("Estimated {:?} in {}", reg, format_duration(t.elapsed()));
+        Ok(reg)
+    });
+
+    // Watching dog
+    std::thread::spawn(move || {
+        let event = match worker_handle.join() {
+            Ok(Ok(reg)) => GuiEvent::Registered(reg),
+            Ok(Err(err)) => {
+                if is_cancelled(&err) {
+                    log!("Cancelled");
+                } else {
+                    log!("Error: {}", err);
+                }
+                GuiEvent::WorkerError
+            }
+            Err(err) => {
+                log!("Error: Worker panicked: {:?}", err);
+                GuiEvent::WorkerError
+            }
+        };
+        GUI_EVENT_TX.get().unwrap().send(event).unwrap();
+    });
+}
+
+fn on_pipeline_action(builder: &Builder, state: &Rc<RefCell<GuiState>>, handler_name: &str) {
+    let mut pipeline = state.borrow().pipeline.clone();
+    match handler_name {
+        "on_pipeline_undo" if !pipeline.undo() => {
+            log!("Error: Nothing to undo");
+            return;
+        }
+        "on_pipeline_redo" if !pipeline.redo() => {
+            log!("Error: Nothing to redo");
+            return;
+        }
+        "on_pipeline_remove" => match selected_step(builder) {
+            Some(idx) => pipeline.remove(idx).unwrap(),
+            None => {
+                log!("Error: No step selected");
+                return;
+            }
+        },
+        "on_pipeline_clear" => pipeline.clear(),
+        _ => {}
+    }
+    pipeline_runner(builder, state, pipeline, false);
+}
+
+fn on_pipeline_edit_args(builder: &Builder, state: &Rc<RefCell<GuiState>>, path: &str, text: &str) {
+    let mut pipeline = state.borrow().pipeline.clone();
+    let ret = path
+        .parse::<usize>()
+        .map_err(failure::Error::from)
+        .and_then(|idx| {
+            let args = serde_json::from_str(text)?;
+            pipeline.set_args(idx, &args)
+        });
+    match ret {
+        Ok(()) => pipeline_runner(builder, state, pipeline, false),
+        Err(err) => log!("Error: {}", err),
+    }
+}
+
+fn selected_step(builder: &Builder) -> Option<usize> {
+    let tree: gtk::TreeView = builder.object("tree_pipeline");
+    let (model, iter) = tree.get_selection().get_selected()?;
+    let path = model.get_path(&iter)?;
+    path.get_indices().first().map(|&idx| idx as usize)
+}
+
+fn update_pipeline_view(builder: &Builder, pipeline: &Pipeline) {
+    let store: gtk::ListStore = builder.object("lst_pipeline");
+    store.clear();
+    for step in pipeline.steps() {
+        store.insert_with_values(None, &[0, 1], &[&step.processor, &step.args.to_string()]);
+    }
+    builder
+        .object::<gtk::Button>("btn_pipeline_undo")
+        .set_sensitive(pipeline.can_undo());
+    builder
+        .object::<gtk::Button>("btn_pipeline_redo")
+        .set_sensitive(pipeline.can_redo());
+}
+
 fn on_select_source_file(builder: &Builder, state: &Rc<RefCell<GuiState>>) {
     let fin: gtk::FileChooser = builder.object("file_input");
     if let Some(file_name) = fin.get_filename() {
@@ -270,16 +558,126 @@ fn on_select_source_file(builder: &Builder, state: &Rc<RefCell<GuiState>>) {
                 state.borrow_mut().image_input = None;
                 img_ctl.set_from_pixbuf(None);
             }
-            Ok((img, pixbuf)) => {
+            Ok(img) => {
+                let pixbuf = img.render(&state.borrow().spectrum_style);
                 log!("Loaded {}x{}", pixbuf.get_width(), pixbuf.get_height());
                 let mut st = state.borrow_mut();
                 auto_rerender(builder, &st, &img_ctl, &pixbuf);
                 st.image_input = Some((img, pixbuf));
+                st.image_output = None;
+                builder
+                    .object::<gtk::Image>("img_output")
+                    .set_from_pixbuf(None);
+                // Re-apply the pipeline on the new input.
+                if !st.pipeline.steps().is_empty() {
+                    let pipeline = st.pipeline.clone();
+                    drop(st);
+                    pipeline_runner(builder, state, pipeline, false);
+                }
             }
         }
     }
 }
 
+/// Load a custom kernel into the entry, with rows joined by `;`.
+fn on_load_filter_kernel(builder: &Builder) {
+    let chooser: gtk::FileChooser = builder.object("file_filter_kernel");
+    let file_name = match chooser.get_filename() {
+        Some(path) => path,
+        None => return,
+    };
+    log!("Loading kernel {}", file_name.display());
+    let text = match std::fs::read_to_string(&file_name) {
+        Ok(text) => text,
+        Err(err) => {
+            log!("Error: {}", err);
+            return;
+        }
+    };
+    let text = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("; ");
+    match parse_matrix(&text) {
+        Ok(kernel) => log!("Loaded {}x{}", kernel.dim().1, kernel.dim().0),
+        Err(err) => log!("Error: {}", err),
+    }
+    builder
+        .object::<gtk::Entry>("ent_filter_kernel")
+        .set_text(&text);
+}
+
+fn on_save_output(builder: &Builder, st: &GuiState) {
+    let img = match &st.image_output {
+        Some((img, _)) => img,
+        None => {
+            log!("Error: No output image");
+            return;
+        }
+    };
+
+    if let Some(file_name) = choose_save_file(builder, "Save output", "output.png") {
+        log!("Saving file {}", file_name.display());
+        match img.save(&file_name) {
+            Ok(()) => log!("Saved"),
+            Err(err) => log!("Error: {}", err),
+        }
+    }
+}
+
+fn on_export_recipe(builder: &Builder, st: &GuiState) {
+    if st.pipeline.steps().is_empty() {
+        log!("Error: Pipeline is empty");
+        return;
+    }
+    if let Some(file_name) = choose_save_file(builder, "Export recipe", "recipe.json") {
+        log!("Exporting recipe {}", file_name.display());
+        match st.pipeline.to_recipe().save(&file_name) {
+            Ok(()) => log!("Exported {} steps", st.pipeline.steps().len()),
+            Err(err) => log!("Error: {}", err),
+        }
+    }
+}
+
+fn choose_save_file(builder: &Builder, title: &str, default_name: &str) -> Option<PathBuf> {
+    let window: ApplicationWindow = builder.object("wnd_main");
+    let dialog = gtk::FileChooserDialog::with_buttons(
+        Some(title),
+        Some(&window),
+        gtk::FileChooserAction::Save,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Save", gtk::ResponseType::Accept),
+        ],
+    );
+    dialog.set_do_overwrite_confirmation(true);
+    dialog.set_current_name(default_name);
+    let resp = dialog.run();
+    let file_name = dialog.get_filename();
+    dialog.destroy();
+
+    match resp {
+        gtk::ResponseType::Accept => file_name,
+        _ => None,
+    }
+}
+
+fn on_spectrum_style_changed(builder: &Builder, st: &mut GuiState) {
+    st.spectrum_style = SpectrumStyle {
+        view: active_choice(builder, "cmb_spectrum_view"),
+        colormap: active_choice(builder, "cmb_spectrum_colormap"),
+    };
+    let style = st.spectrum_style;
+    for (img, pixbuf) in st.image_input.iter_mut().chain(st.image_output.iter_mut()) {
+        if let Image::Complex(_) = img {
+            *pixbuf = img.render(&style);
+        }
+    }
+    on_resize(builder, st, true);
+}
+
 fn on_resize(builder: &Builder, st: &GuiState, force: bool) {
     if force || st.auto_shrink {
         if let Some((_, pixbuf)) = &st.image_input {
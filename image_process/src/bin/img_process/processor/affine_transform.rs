@@ -0,0 +1,57 @@
+use crate::util::BuilderExtManualExt as _;
+use gtk::{prelude::*, Builder};
+use vision_works::{
+    affine::{AffineParams, AffineTransform},
+    processor::{Args, Params as _},
+};
+
+impl super::Widgets for AffineTransform {
+    fn register_handler(
+        &self,
+        builder: &Builder,
+        handler_name: &str,
+        run: Box<dyn Fn(Args) + 'static>,
+    ) -> Option<Box<dyn Fn() + 'static>> {
+        let builder = builder.clone();
+        match handler_name {
+            "on_affine_trans_reset" => Some(Box::new(move || {
+                builder
+                    .object::<gtk::Scale>("scl_affine_trans_scale")
+                    .set_value(1.0);
+                builder
+                    .object::<gtk::Scale>("scl_affine_trans_rotate")
+                    .set_value(0.0);
+                builder
+                    .object::<gtk::Scale>("scl_affine_trans_shift_x")
+                    .set_value(0.0);
+                builder
+                    .object::<gtk::Scale>("scl_affine_trans_shift_y")
+                    .set_value(0.0);
+            })),
+            "on_affine_trans_run" => Some(Box::new(move || {
+                run(get_params(&builder, false).to_args());
+            })),
+            "on_affine_trans_run_ocl" => Some(Box::new(move || {
+                run(get_params(&builder, true).to_args());
+            })),
+            _ => None,
+        }
+    }
+}
+
+fn get_params(builder: &Builder, ocl: bool) -> AffineParams {
+    let scale: gtk::Scale = builder.object("scl_affine_trans_scale");
+    let rotate: gtk::Scale = builder.object("scl_affine_trans_rotate");
+    let shift_x: gtk::Scale = builder.object("scl_affine_trans_shift_x");
+    let shift_y: gtk::Scale = builder.object("scl_affine_trans_shift_y");
+    AffineParams {
+        scale: scale.get_value() as f32,
+        rotate: rotate.get_value() as f32,
+        shift_x: shift_x.get_value() as f32,
+        shift_y: shift_y.get_value() as f32,
+        keep_size: builder
+            .object::<gtk::ToggleButton>("chk_affine_trans_keep_size")
+            .get_active(),
+        ocl,
+    }
+}
@@ -0,0 +1,51 @@
+use crate::util::BuilderExtManualExt as _;
+use gtk::{prelude::*, Builder};
+use vision_works::{
+    dct::{DCTParams, JpegParams, JpegQuantize, DCT},
+    processor::{Args, Params as _},
+};
+
+impl super::Widgets for DCT {
+    fn register_handler(
+        &self,
+        builder: &Builder,
+        handler_name: &str,
+        run: Box<dyn Fn(Args) + 'static>,
+    ) -> Option<Box<dyn Fn() + 'static>> {
+        let inverse = match handler_name {
+            "on_dct_dct" => false,
+            "on_dct_idct" => true,
+            _ => return None,
+        };
+        let builder = builder.clone();
+        Some(Box::new(move || {
+            let block = builder.object::<gtk::Scale>("scl_dct_block").get_value();
+            let params = DCTParams {
+                inverse,
+                block: block.round() as usize,
+            };
+            run(params.to_args())
+        }))
+    }
+}
+
+impl super::Widgets for JpegQuantize {
+    fn register_handler(
+        &self,
+        builder: &Builder,
+        handler_name: &str,
+        run: Box<dyn Fn(Args) + 'static>,
+    ) -> Option<Box<dyn Fn() + 'static>> {
+        let builder = builder.clone();
+        match handler_name {
+            "on_jpeg_run" => Some(Box::new(move || {
+                let quality = builder.object::<gtk::Scale>("scl_jpeg_quality").get_value();
+                let params = JpegParams {
+                    quality: quality.round() as u32,
+                };
+                run(params.to_args())
+            })),
+            _ => None,
+        }
+    }
+}
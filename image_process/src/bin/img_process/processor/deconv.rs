@@ -0,0 +1,34 @@
+use crate::util::BuilderExtManualExt as _;
+use gtk::{prelude::*, Builder};
+use vision_works::{
+    deconv::{Deconv, DeconvParams},
+    processor::{Args, Params as _},
+};
+
+impl super::Widgets for Deconv {
+    fn register_handler(
+        &self,
+        builder: &Builder,
+        handler_name: &str,
+        run: Box<dyn Fn(Args) + 'static>,
+    ) -> Option<Box<dyn Fn() + 'static>> {
+        let builder = builder.clone();
+        match handler_name {
+            "on_deconv_run" => Some(Box::new(move || {
+                let value = |name: &str| builder.object::<gtk::Scale>(name).get_value() as f32;
+                let params = DeconvParams {
+                    ty: super::active_choice(&builder, "cmb_deconv_type"),
+                    psf: super::active_choice(&builder, "cmb_deconv_psf"),
+                    length: value("scl_deconv_length"),
+                    angle: value("scl_deconv_angle"),
+                    radius: value("scl_deconv_radius"),
+                    snr: value("scl_deconv_snr"),
+                    threshold: value("scl_deconv_threshold"),
+                    iterations: value("scl_deconv_iterations").round() as u32,
+                };
+                run(params.to_args())
+            })),
+            _ => None,
+        }
+    }
+}
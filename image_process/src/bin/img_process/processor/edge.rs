@@ -0,0 +1,36 @@
+use crate::util::BuilderExtManualExt as _;
+use gtk::{prelude::*, Builder};
+use vision_works::{
+    edge::{Edge, EdgeParams, EdgeType},
+    processor::{Args, Params as _},
+};
+
+impl super::Widgets for Edge {
+    fn register_handler(
+        &self,
+        builder: &Builder,
+        handler_name: &str,
+        run: Box<dyn Fn(Args) + 'static>,
+    ) -> Option<Box<dyn Fn() + 'static>> {
+        let ty = match handler_name {
+            "on_edge_sobel" => EdgeType::Sobel,
+            "on_edge_prewitt" => EdgeType::Prewitt,
+            "on_edge_scharr" => EdgeType::Scharr,
+            "on_edge_log" => EdgeType::LaplacianOfGaussian,
+            "on_edge_canny" => EdgeType::Canny,
+            _ => return None,
+        };
+        let builder = builder.clone();
+        Some(Box::new(move || {
+            let value = |name: &str| builder.object::<gtk::Scale>(name).get_value() as f32;
+            let params = EdgeParams {
+                ty,
+                output: super::active_choice(&builder, "cmb_edge_output"),
+                sigma: value("scl_edge_sigma"),
+                low: value("scl_edge_low"),
+                high: value("scl_edge_high"),
+            };
+            run(params.to_args())
+        }))
+    }
+}
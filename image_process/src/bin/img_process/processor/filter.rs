@@ -0,0 +1,77 @@
+use crate::util::BuilderExtManualExt as _;
+use gtk::{prelude::*, Builder};
+use vision_works::{
+    filter::{Filter, FilterParams, FilterType},
+    processor::{Args, Params as _},
+};
+
+impl super::Widgets for Filter {
+    fn register_handler(
+        &self,
+        builder: &Builder,
+        handler_name: &str,
+        run: Box<dyn Fn(Args) + 'static>,
+    ) -> Option<Box<dyn Fn() + 'static>> {
+        let on_filter = |ty: FilterType| {
+            let builder = builder.clone();
+            Box::new(move || {
+                let neighbor = builder
+                    .object::<gtk::Scale>("scl_filter_neighbor")
+                    .get_value()
+                    .round() as usize;
+                let sigma = builder
+                    .object::<gtk::Scale>("scl_filter_gauss_sigma")
+                    .get_value() as f32;
+                let sigma_d = builder
+                    .object::<gtk::Scale>("scl_filter_bilateral_sigma_d")
+                    .get_value() as f32;
+                let sigma_r = builder
+                    .object::<gtk::Scale>("scl_filter_bilateral_sigma_r")
+                    .get_value() as f32;
+                let percentile = builder
+                    .object::<gtk::Scale>("scl_filter_percentile")
+                    .get_value() as f32;
+                let border_value = builder
+                    .object::<gtk::Scale>("scl_filter_border_value")
+                    .get_value() as f32;
+                let kernel = builder
+                    .object::<gtk::Entry>("ent_filter_kernel")
+                    .get_text()
+                    .map(|s| s.to_string())
+                    .unwrap_or_default();
+                let normalize = builder
+                    .object::<gtk::ToggleButton>("chk_filter_normalize")
+                    .get_active();
+                let params = FilterParams {
+                    ty,
+                    neighbor,
+                    sigma,
+                    sigma_d,
+                    sigma_r,
+                    percentile,
+                    border: super::active_choice(&builder, "cmb_filter_border"),
+                    border_value,
+                    kernel,
+                    normalize,
+                };
+                run(params.to_args());
+            })
+        };
+
+        match handler_name {
+            "on_filter_run_box" => Some(on_filter(FilterType::Box)),
+            "on_filter_run_gauss" => Some(on_filter(FilterType::Gaussian)),
+            "on_filter_run_gauss_ocl" => Some(on_filter(FilterType::GaussianCL)),
+            "on_filter_run_wiener" => Some(on_filter(FilterType::Wiener)),
+            "on_filter_run_bilateral" => Some(on_filter(FilterType::Bilateral)),
+            "on_filter_run_median" => Some(on_filter(FilterType::Median)),
+            "on_filter_run_min" => Some(on_filter(FilterType::Min)),
+            "on_filter_run_max" => Some(on_filter(FilterType::Max)),
+            "on_filter_run_percentile" => Some(on_filter(FilterType::Percentile)),
+            "on_filter_run_adaptive_median" => Some(on_filter(FilterType::AdaptiveMedian)),
+            "on_filter_run_custom" => Some(on_filter(FilterType::Custom)),
+            "on_filter_run_custom_ocl" => Some(on_filter(FilterType::CustomCL)),
+            _ => None,
+        }
+    }
+}
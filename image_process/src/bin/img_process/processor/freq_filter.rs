@@ -0,0 +1,33 @@
+use crate::util::BuilderExtManualExt as _;
+use gtk::{prelude::*, Builder};
+use vision_works::{
+    freq_filter::{FreqFilter, FreqFilterParams},
+    processor::{Args, Params as _},
+};
+
+impl super::Widgets for FreqFilter {
+    fn register_handler(
+        &self,
+        builder: &Builder,
+        handler_name: &str,
+        run: Box<dyn Fn(Args) + 'static>,
+    ) -> Option<Box<dyn Fn() + 'static>> {
+        let builder = builder.clone();
+        match handler_name {
+            "on_freq_filter_run" => Some(Box::new(move || {
+                let value = |name: &str| builder.object::<gtk::Scale>(name).get_value() as f32;
+                let params = FreqFilterParams {
+                    ty: super::active_choice(&builder, "cmb_freq_filter_type"),
+                    shape: super::active_choice(&builder, "cmb_freq_filter_shape"),
+                    cutoff: value("scl_freq_filter_cutoff"),
+                    width: value("scl_freq_filter_width"),
+                    order: value("scl_freq_filter_order").round() as u32,
+                    notch_u: value("scl_freq_filter_notch_u"),
+                    notch_v: value("scl_freq_filter_notch_v"),
+                };
+                run(params.to_args())
+            })),
+            _ => None,
+        }
+    }
+}
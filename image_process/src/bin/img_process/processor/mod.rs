@@ -1,28 +1,73 @@
-use crate::util::{Image, Result};
-use gtk::Builder;
-use std::{any::Any, sync::Arc};
+use crate::util::BuilderExtManualExt as _;
+use gtk::{prelude::*, Builder};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use vision_works::processor::{Args, ImageProcessor};
 
 mod affine_transform;
+mod dct;
+mod deconv;
 mod dft;
+mod edge;
 mod filter;
+mod freq_filter;
+mod morphology;
 mod noise;
 
-pub trait ImageProcessor: Send + Sync {
+/// GUI handlers of a processor from the library.
+pub trait Widgets: Sync {
     fn register_handler(
         &self,
         builder: &Builder,
         handler_name: &str,
-        run: Box<dyn Fn(Box<dyn Any + Send>) + 'static>,
+        run: Box<dyn Fn(Args) + 'static>,
     ) -> Option<Box<dyn Fn() + 'static>>;
+}
+
+/// Processors of the library registry with their widgets. Those without widgets are left out.
+pub fn load_processors() -> Vec<(Arc<dyn ImageProcessor>, &'static dyn Widgets)> {
+    vision_works::processor::load_processors()
+        .into_iter()
+        .filter_map(|pro| {
+            let widgets = widgets(pro.name())?;
+            Some((pro, widgets))
+        })
+        .collect()
+}
 
-    fn run(&self, args: Box<dyn Any + Send>, src: Image) -> Result<Image>;
+fn widgets(name: &str) -> Option<&'static dyn Widgets> {
+    use vision_works::{
+        affine::AffineTransform,
+        dct::{JpegQuantize, DCT},
+        deconv::Deconv,
+        dft::DFT,
+        edge::Edge,
+        filter::Filter,
+        freq_filter::FreqFilter,
+        morphology::Morphology,
+        noise::Noise,
+        processor::Processor,
+    };
+    Some(match name {
+        AffineTransform::NAME => &AffineTransform,
+        DFT::NAME => &DFT,
+        Noise::NAME => &Noise,
+        Filter::NAME => &Filter,
+        FreqFilter::NAME => &FreqFilter,
+        Deconv::NAME => &Deconv,
+        DCT::NAME => &DCT,
+        JpegQuantize::NAME => &JpegQuantize,
+        Morphology::NAME => &Morphology,
+        Edge::NAME => &Edge,
+        _ => return None,
+    })
 }
 
-pub fn load_processors() -> Vec<Arc<dyn ImageProcessor>> {
-    vec![
-        Arc::new(affine_transform::AffineTransform),
-        Arc::new(dft::DFT),
-        Arc::new(noise::Noise),
-        Arc::new(filter::Filter),
-    ]
+/// Parse the active id of a `GtkComboBoxText` as a choice parameter.
+pub fn active_choice<T: DeserializeOwned>(builder: &Builder, name: &str) -> T {
+    let id = builder
+        .object::<gtk::ComboBoxText>(name)
+        .get_active_id()
+        .unwrap_or_else(|| panic!("No active item in `{}`", name));
+    serde_json::from_value(id.as_str().into()).expect("Invalid choice")
 }
@@ -0,0 +1,47 @@
+use crate::util::BuilderExtManualExt as _;
+use gtk::{prelude::*, Builder};
+use vision_works::{
+    morphology::{MorphOp, MorphParams, Morphology},
+    processor::{Args, Params as _},
+};
+
+impl super::Widgets for Morphology {
+    fn register_handler(
+        &self,
+        builder: &Builder,
+        handler_name: &str,
+        run: Box<dyn Fn(Args) + 'static>,
+    ) -> Option<Box<dyn Fn() + 'static>> {
+        let op = match handler_name {
+            "on_morph_erode" => MorphOp::Erode,
+            "on_morph_dilate" => MorphOp::Dilate,
+            "on_morph_open" => MorphOp::Open,
+            "on_morph_close" => MorphOp::Close,
+            "on_morph_gradient" => MorphOp::Gradient,
+            "on_morph_top_hat" => MorphOp::TopHat,
+            "on_morph_black_hat" => MorphOp::BlackHat,
+            _ => return None,
+        };
+        let builder = builder.clone();
+        Some(Box::new(move || {
+            let value = |name: &str| builder.object::<gtk::Scale>(name).get_value() as f32;
+            let custom = builder
+                .object::<gtk::Entry>("ent_morph_custom")
+                .get_text()
+                .map(|s| s.to_string())
+                .unwrap_or_default();
+            let params = MorphParams {
+                op,
+                shape: super::active_choice(&builder, "cmb_morph_shape"),
+                size: value("scl_morph_size").round() as usize,
+                custom,
+                binary: builder
+                    .object::<gtk::ToggleButton>("chk_morph_binary")
+                    .get_active(),
+                border: super::active_choice(&builder, "cmb_morph_border"),
+                border_value: value("scl_morph_border_value"),
+            };
+            run(params.to_args())
+        }))
+    }
+}
@@ -0,0 +1,341 @@
+use crate::{
+    dft::FFT,
+    image::{color_channels, Image},
+    processor::{Job, ParamKind, ParamSpec, Params, Processor},
+    Result,
+};
+use ndarray::{prelude::*, Zip};
+use num_complex::Complex32 as C;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// DCT of each channel. Coefficients are stored as the real part of a complex image.
+pub struct DCT;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DCTParams {
+    pub inverse: bool,
+    /// Size of square blocks, or 0 to transform the whole image.
+    pub block: usize,
+}
+
+impl Params for DCTParams {
+    const SPECS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "inverse",
+            desc: "Run IDCT on a complex image",
+            kind: ParamKind::Bool { default: false },
+        },
+        ParamSpec {
+            name: "block",
+            desc: "Size of square blocks, or 0 to transform the whole image",
+            kind: ParamKind::Int {
+                min: 0,
+                max: 256,
+                default: 8,
+            },
+        },
+    ];
+}
+
+impl Processor for DCT {
+    const NAME: &'static str = "dct";
+    type Params = DCTParams;
+
+    fn run(&self, params: &DCTParams, src: Image, job: &Job) -> Result<Image> {
+        if !params.inverse {
+            let dest = dct(src.expect_normal()?, params.block, false, job)?;
+            Ok(Image::Complex(dest.mapv(C::from)))
+        } else {
+            let src = src.expect_complex()?.mapv(|v| v.re);
+            Ok(Image::Normal(dct(src, params.block, true, job)?))
+        }
+    }
+}
+
+/// Quantize DCT coefficients of 8x8 blocks as JPEG does, and reconstruct the image.
+/// Reports the coefficients kept and PSNR to the job.
+pub struct JpegQuantize;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JpegParams {
+    pub quality: u32,
+}
+
+impl Params for JpegParams {
+    const SPECS: &'static [ParamSpec] = &[ParamSpec {
+        name: "quality",
+        desc: "Quality factor as in JPEG",
+        kind: ParamKind::Int {
+            min: 1,
+            max: 100,
+            default: 50,
+        },
+    }];
+}
+
+impl Processor for JpegQuantize {
+    const NAME: &'static str = "jpeg";
+    type Params = JpegParams;
+
+    fn run(&self, params: &JpegParams, src: Image, job: &Job) -> Result<Image> {
+        let src = src.expect_normal()?;
+        let (h, w, ncol) = src.dim();
+        // Alpha is kept.
+        let ncolor = color_channels(ncol);
+        let table = quantization_table(params.quality);
+
+        let mut dest = src.clone();
+        let (mut kept, mut bits) = (0, 0);
+        for col in 0..ncolor {
+            let job = job.part(col, ncolor);
+            // Level shift of 8-bit samples.
+            let chan = src.index_axis(Axis(2), col).mapv(|v| v * 255.0 - 128.0);
+            let mut coefs = block_dct_2d(chan, 8, false, &job.part(0, 2))?;
+            Zip::indexed(&mut coefs).apply(|(x, y), v| {
+                let step = table[[x % 8, y % 8]];
+                let q = (*v / step).round();
+                if q != 0.0 {
+                    kept += 1;
+                    // Magnitude and sign.
+                    bits += 32 - (q.abs() as u32).leading_zeros() + 1;
+                }
+                *v = q * step;
+            });
+            let chan = block_dct_2d(coefs, 8, true, &job.part(1, 2))?;
+            dest.index_axis_mut(Axis(2), col)
+                .assign(&chan.mapv(|v| (v + 128.0) / 255.0));
+        }
+
+        let color = s![.., .., ..ncolor];
+        let diff = &dest.slice(color) - &src.slice(color);
+        let mse = diff.mapv(|v| v * v).mean().unwrap();
+        let total = h * w * ncolor;
+        job.log(&format!(
+            "Kept {} of {} coefficients ({:.2}%), {:.3} bits per pixel, PSNR {:.2} dB",
+            kept,
+            total,
+            kept as f32 / total as f32 * 100.0,
+            bits as f32 / (h * w) as f32,
+            -10.0 * mse.log10(),
+        ));
+        Ok(Image::Normal(dest))
+    }
+}
+
+/// Luminance quantization table of JPEG, scaled by `quality` in [1, 100] as libjpeg does.
+pub fn quantization_table(quality: u32) -> Array2<f32> {
+    #[rustfmt::skip]
+    const LUMA: [[u32; 8]; 8] = [
+        [16, 11, 10, 16,  24,  40,  51,  61],
+        [12, 12, 14, 19,  26,  58,  60,  55],
+        [14, 13, 16, 24,  40,  57,  69,  56],
+        [14, 17, 22, 29,  51,  87,  80,  62],
+        [18, 22, 37, 56,  68, 109, 103,  77],
+        [24, 35, 55, 64,  81, 104, 113,  92],
+        [49, 64, 78, 87, 103, 121, 120, 101],
+        [72, 92, 95, 98, 112, 100, 103,  99],
+    ];
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - 2 * quality
+    };
+    Array::from_shape_fn((8, 8), |(x, y)| {
+        ((LUMA[x][y] * scale + 50) / 100).max(1) as f32
+    })
+}
+
+/// Run 2D-DCT on each channel, by blocks of `block` or on the whole image if `block` is 0.
+pub fn dct(mut src: Array3<f32>, block: usize, inverse: bool, job: &Job) -> Result<Array3<f32>> {
+    let ncol = src.dim().2;
+    for col in 0..ncol {
+        let job = job.part(col, ncol);
+        let chan = src.index_axis(Axis(2), col).to_owned();
+        let chan = if block == 0 {
+            dct_2d(chan, inverse, &job)?
+        } else {
+            block_dct_2d(chan, block, inverse, &job)?
+        };
+        src.index_axis_mut(Axis(2), col).assign(&chan);
+    }
+    Ok(src)
+}
+
+/// Orthonormal 1D-DCT-II of a fixed size, whose inverse is DCT-III.
+///
+/// Computed by FFT of the same size on reordered samples (Makhoul's algorithm).
+#[derive(Debug)]
+pub struct DCTPlan {
+    n: usize,
+    fft: Arc<FFT>,
+    /// e^(-iπk/2n)
+    twiddle: Vec<C>,
+}
+
+impl DCTPlan {
+    pub fn init(n: usize) -> Self {
+        let theta = -std::f32::consts::PI / (2 * n) as f32;
+        let twiddle = (0..n)
+            .map(|k| C::from_polar(&1.0, &(theta * k as f32)))
+            .collect();
+        Self {
+            n,
+            fft: FFT::cached(n),
+            twiddle,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.n
+    }
+
+    pub fn dct(&self, mut data: ArrayViewMut1<f32>, inverse: bool) {
+        let n = self.n;
+        assert_eq!(data.len(), n);
+        // Even samples in order, followed by odd samples in reverse order.
+        let reorder = |i: usize| {
+            if i < n - n / 2 {
+                2 * i
+            } else {
+                2 * (n - 1 - i) + 1
+            }
+        };
+        // Factor of the orthonormal basis.
+        let scale = |k: usize| {
+            if k == 0 {
+                1.0
+            } else {
+                std::f32::consts::SQRT_2
+            }
+        };
+
+        let mut buf = Array1::zeros(n);
+        if !inverse {
+            for i in 0..n {
+                buf[i] = C::from(data[reorder(i)]);
+            }
+            self.fft.fft(buf.view_mut(), false);
+            for k in 0..n {
+                data[k] = (self.twiddle[k] * buf[k]).re * scale(k);
+            }
+        } else {
+            let y = |k: usize| if k < n { data[k] / scale(k) } else { 0.0 };
+            for k in 0..n {
+                buf[k] = self.twiddle[k].conj() * C::new(y(k), -y(n - k));
+            }
+            self.fft.fft(buf.view_mut(), true);
+            for i in 0..n {
+                data[reorder(i)] = buf[i].re;
+            }
+        }
+    }
+}
+
+/// 2D-DCT of the whole matrix.
+pub fn dct_2d(mut mat: Array2<f32>, inverse: bool, job: &Job) -> Result<Array2<f32>> {
+    let (n, m) = mat.dim();
+    let (f1, f2) = (DCTPlan::init(n), DCTPlan::init(m));
+    let progress = job.progress(n + m);
+    // Run 1D-DCT for each row and then for each column.
+    for &(axis, f) in &[(1, &f2), (0, &f1)] {
+        mat.axis_iter_mut(Axis(1 - axis))
+            .into_par_iter()
+            .for_each(|lane| {
+                if !job.is_cancelled() {
+                    f.dct(lane, inverse);
+                    progress.tick();
+                }
+            });
+        job.check()?;
+    }
+    Ok(mat)
+}
+
+/// 2D-DCT of each `block`x`block` block. Blocks at the bottom and right borders may be smaller.
+pub fn block_dct_2d(
+    mut mat: Array2<f32>,
+    block: usize,
+    inverse: bool,
+    job: &Job,
+) -> Result<Array2<f32>> {
+    let (h, w) = mat.dim();
+    let plans: Vec<DCTPlan> = [block.min(h), block.min(w), h % block, w % block]
+        .iter()
+        .filter(|&&n| n > 0)
+        .map(|&n| DCTPlan::init(n))
+        .collect();
+    let plan = |n: usize| plans.iter().find(|f| f.size() == n).unwrap();
+
+    let mut strips: Vec<_> = mat.axis_chunks_iter_mut(Axis(0), block).collect();
+    let progress = job.progress(strips.len());
+    strips.par_iter_mut().for_each(|strip| {
+        if job.is_cancelled() {
+            return;
+        }
+        for mut blk in strip.axis_chunks_iter_mut(Axis(1), block) {
+            let (bh, bw) = blk.dim();
+            for row in blk.genrows_mut() {
+                plan(bw).dct(row, inverse);
+            }
+            for col in blk.gencolumns_mut() {
+                plan(bh).dct(col, inverse);
+            }
+        }
+        progress.tick();
+    });
+    job.check()?;
+    Ok(mat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dct() {
+        for &n in &[1, 2, 3, 5, 8, 13] {
+            let src = Array::from_shape_fn(n, |i| (i * 7 % 5) as f32 - 2.0);
+            let mut dest = src.clone();
+            let f = DCTPlan::init(n);
+            f.dct(dest.view_mut(), false);
+            for k in 0..n {
+                let c = if k == 0 {
+                    (1.0 / n as f32).sqrt()
+                } else {
+                    (2.0 / n as f32).sqrt()
+                };
+                let expect: f32 = (0..n)
+                    .map(|i| {
+                        let t = std::f32::consts::PI * ((2 * i + 1) * k) as f32 / (2 * n) as f32;
+                        src[i] * t.cos() * c
+                    })
+                    .sum();
+                assert!((dest[k] - expect).abs() < 1e-4, "n={} k={}", n, k);
+            }
+            f.dct(dest.view_mut(), true);
+            assert!(src.iter().zip(&dest).all(|(a, b)| (a - b).abs() < 1e-4));
+        }
+
+        let job = Job::default();
+        let src = Array::from_shape_fn((13, 20, 1), |(x, y, _)| {
+            ((x * 3 + y * y) % 17) as f32 / 17.0
+        });
+        for &block in &[0, 8] {
+            let coefs = dct(src.clone(), block, false, &job).unwrap();
+            let dest = dct(coefs, block, true, &job).unwrap();
+            assert!(src.iter().zip(&dest).all(|(a, b)| (a - b).abs() < 1e-4));
+        }
+
+        let error = |quality| {
+            let dest = JpegQuantize
+                .run(&JpegParams { quality }, Image::Normal(src.clone()), &job)
+                .unwrap()
+                .expect_normal()
+                .unwrap();
+            (&dest - &src).mapv(f32::abs).sum()
+        };
+        assert!(error(90) < error(10));
+    }
+}
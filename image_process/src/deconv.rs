@@ -0,0 +1,337 @@
+use crate::{
+    dft::fft_2d,
+    image::{color_channels, Image},
+    processor::{Job, ParamKind, ParamSpec, Params, Processor},
+    Result,
+};
+use failure::ensure;
+use ndarray::{prelude::*, Zip};
+use num_complex::Complex32 as C;
+use serde::{Deserialize, Serialize};
+
+/// Restore images blurred by a known point spread function (PSF).
+/// The blur is assumed to be circular, so borders may ring.
+pub struct Deconv;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeconvType {
+    /// Convolve with the PSF, to simulate the degradation.
+    Blur,
+    Inverse,
+    Wiener,
+    RichardsonLucy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PsfShape {
+    /// Uniform linear motion.
+    Motion,
+    /// Out-of-focus blur.
+    Disk,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeconvParams {
+    #[serde(rename = "type")]
+    pub ty: DeconvType,
+    pub psf: PsfShape,
+    pub length: f32,
+    /// Direction of motion in degrees, counterclockwise from the horizontal.
+    pub angle: f32,
+    pub radius: f32,
+    pub snr: f32,
+    pub threshold: f32,
+    pub iterations: u32,
+}
+
+impl Params for DeconvParams {
+    const SPECS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "type",
+            desc: "Restoration method, or `blur` to apply the PSF",
+            kind: ParamKind::Choice {
+                choices: &["blur", "inverse", "wiener", "richardson_lucy"],
+                default: "wiener",
+            },
+        },
+        ParamSpec {
+            name: "psf",
+            desc: "Shape of the point spread function",
+            kind: ParamKind::Choice {
+                choices: &["motion", "disk"],
+                default: "motion",
+            },
+        },
+        ParamSpec {
+            name: "length",
+            desc: "Length of motion in pixels",
+            kind: ParamKind::Float {
+                min: 1.0,
+                max: 500.0,
+                default: 15.0,
+            },
+        },
+        ParamSpec {
+            name: "angle",
+            desc: "Direction of motion in degrees, counterclockwise from the horizontal",
+            kind: ParamKind::Float {
+                min: -180.0,
+                max: 180.0,
+                default: 0.0,
+            },
+        },
+        ParamSpec {
+            name: "radius",
+            desc: "Radius of the disk in pixels",
+            kind: ParamKind::Float {
+                min: 0.5,
+                max: 200.0,
+                default: 5.0,
+            },
+        },
+        ParamSpec {
+            name: "snr",
+            desc: "Signal-to-noise power ratio of Wiener deconvolution",
+            kind: ParamKind::Float {
+                min: 0.01,
+                max: 1e8,
+                default: 100.0,
+            },
+        },
+        ParamSpec {
+            name: "threshold",
+            desc: "Frequencies with gain not exceeding it are kept as is by inverse filtering",
+            kind: ParamKind::Float {
+                min: 0.0,
+                max: 1.0,
+                default: 0.05,
+            },
+        },
+        ParamSpec {
+            name: "iterations",
+            desc: "Iterations of Richardson-Lucy deconvolution",
+            kind: ParamKind::Int {
+                min: 1,
+                max: 1000,
+                default: 30,
+            },
+        },
+    ];
+}
+
+impl DeconvParams {
+    pub fn psf(&self) -> Array2<f32> {
+        match self.psf {
+            PsfShape::Motion => motion_psf(self.length, self.angle.to_radians()),
+            PsfShape::Disk => disk_psf(self.radius),
+        }
+    }
+}
+
+impl Processor for Deconv {
+    const NAME: &'static str = "deconv";
+    type Params = DeconvParams;
+
+    fn run(&self, params: &DeconvParams, src: Image, job: &Job) -> Result<Image> {
+        let mut mat = src.expect_normal()?;
+        let (h, w, ncol) = mat.dim();
+        let psf = params.psf();
+        let (kh, kw) = psf.dim();
+        ensure!(
+            kh <= h && kw <= w,
+            "PSF of {}x{} is larger than the image",
+            kw,
+            kh
+        );
+
+        // Alpha is kept.
+        let ncolor = color_channels(ncol);
+        let otf = transfer_function(&psf, h, w, &job.part(0, ncolor + 1))?;
+        for col in 0..ncolor {
+            let job = job.part(col + 1, ncolor + 1);
+            let chan = mat.index_axis(Axis(2), col).to_owned();
+            let chan = match params.ty {
+                DeconvType::Blur => filter_spectrum(chan, &otf, &job, |g, h| g * h)?,
+                DeconvType::Inverse => inverse_filter(chan, &otf, params.threshold, &job)?,
+                DeconvType::Wiener => wiener_deconv(chan, &otf, params.snr, &job)?,
+                DeconvType::RichardsonLucy => {
+                    richardson_lucy(chan, &otf, params.iterations as usize, &job)?
+                }
+            };
+            mat.index_axis_mut(Axis(2), col).assign(&chan);
+        }
+        Ok(Image::Normal(mat))
+    }
+}
+
+/// Anti-aliased line segment of `length` through the center, in direction `angle` in radians.
+/// Normalized to sum 1.
+pub fn motion_psf(length: f32, angle: f32) -> Array2<f32> {
+    let size = 2 * (length / 2.0).ceil() as usize + 1;
+    let c = (size / 2) as f32;
+    // Rows go downwards.
+    let (dx, dy) = (-angle.sin(), angle.cos());
+    let psf = Array::from_shape_fn((size, size), |(x, y)| {
+        let (x, y) = (x as f32 - c, y as f32 - c);
+        let perp = (x * dy - y * dx).abs();
+        let along = (x * dx + y * dy).abs() - length / 2.0;
+        let dist = if along > 0.0 { along.hypot(perp) } else { perp };
+        (1.0 - dist).max(0.0)
+    });
+    let sum = psf.sum();
+    psf / sum
+}
+
+/// Uniform disk of `radius` with anti-aliased edges. Normalized to sum 1.
+pub fn disk_psf(radius: f32) -> Array2<f32> {
+    let size = 2 * radius.ceil() as usize + 1;
+    let c = (size / 2) as f32;
+    let psf = Array::from_shape_fn((size, size), |(x, y)| {
+        let v = radius + 0.5 - (x as f32 - c).hypot(y as f32 - c);
+        if v >= 1.0 {
+            1.0
+        } else {
+            v.max(0.0)
+        }
+    });
+    let sum = psf.sum();
+    psf / sum
+}
+
+/// Unnormalized DFT of the PSF padded to (h, w), with the center of PSF at the origin.
+/// Multiplying it with a spectrum from `fft_2d` is circular convolution.
+pub fn transfer_function(psf: &Array2<f32>, h: usize, w: usize, job: &Job) -> Result<Array2<C>> {
+    let (kh, kw) = psf.dim();
+    let (cx, cy) = (kh / 2, kw / 2);
+    let mut pad = Array::zeros((h, w));
+    for ((x, y), &v) in psf.indexed_iter() {
+        pad[[(x + h - cx) % h, (y + w - cy) % w]] += C::from(v);
+    }
+    // Compensate the normalization of FFT.
+    let scale = ((h * w) as f32).sqrt();
+    Ok(fft_2d(pad, false, job)? * C::from(scale))
+}
+
+/// Map each frequency by `f(G, H)` where G is the spectrum of `mat` and H is `otf`.
+fn filter_spectrum(
+    mat: Array2<f32>,
+    otf: &Array2<C>,
+    job: &Job,
+    f: impl Fn(C, C) -> C + Sync,
+) -> Result<Array2<f32>> {
+    let mut spectrum = fft_2d(mat.mapv(C::from), false, &job.part(0, 2))?;
+    Zip::from(&mut spectrum)
+        .and(otf)
+        .par_apply(|g, &h| *g = f(*g, h));
+    Ok(fft_2d(spectrum, true, &job.part(1, 2))?.mapv(|v| v.re))
+}
+
+/// Divide by H where its gain exceeds `threshold`, to avoid amplifying noise unboundedly.
+/// Frequencies of zero gain are always kept.
+pub fn inverse_filter(
+    mat: Array2<f32>,
+    otf: &Array2<C>,
+    threshold: f32,
+    job: &Job,
+) -> Result<Array2<f32>> {
+    filter_spectrum(
+        mat,
+        otf,
+        job,
+        |g, h| {
+            if h.norm() > threshold {
+                g / h
+            } else {
+                g
+            }
+        },
+    )
+}
+
+/// Minimize the mean square error, assuming constant power ratio `snr` of signal to noise.
+pub fn wiener_deconv(
+    mat: Array2<f32>,
+    otf: &Array2<C>,
+    snr: f32,
+    job: &Job,
+) -> Result<Array2<f32>> {
+    filter_spectrum(mat, otf, job, |g, h| {
+        g * h.conj() / (h.norm_sqr() + 1.0 / snr)
+    })
+}
+
+/// Maximum likelihood estimation under Poisson noise, by multiplicative updates.
+pub fn richardson_lucy(
+    mat: Array2<f32>,
+    otf: &Array2<C>,
+    iterations: usize,
+    job: &Job,
+) -> Result<Array2<f32>> {
+    const EPS: f32 = 1e-6;
+    let observed = mat.mapv(|v| v.max(0.0));
+    let mut estimate = observed.clone();
+    for i in 0..iterations {
+        let job = job.part(i, iterations);
+        let blurred = filter_spectrum(estimate.clone(), otf, &job.part(0, 2), |g, h| g * h)?;
+        let ratio = &observed / &blurred.mapv(|v| v.max(EPS));
+        // Correlate with the PSF, ie. convolve with the flipped one.
+        let correction = filter_spectrum(ratio, otf, &job.part(1, 2), |g, h| g * h.conj())?;
+        estimate *= &correction;
+    }
+    Ok(estimate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deconv() {
+        let job = Job::default();
+        assert!((motion_psf(7.0, 0.3).sum() - 1.0).abs() < 1e-5);
+        assert_eq!(disk_psf(2.0).dim(), (5, 5));
+
+        let src = Array::from_shape_fn((32, 40, 1), |(x, y, _)| {
+            ((8..20).contains(&x) && (10..30).contains(&y)) as u8 as f32 * 0.6 + 0.2
+        });
+        let run = |ty, src: Array3<f32>| {
+            let params = DeconvParams {
+                ty,
+                length: 7.0,
+                angle: 30.0,
+                threshold: 1e-3,
+                snr: 1e4,
+                iterations: 100,
+                ..DeconvParams::defaults()
+            };
+            Deconv
+                .run(&params, Image::Normal(src), &job)
+                .unwrap()
+                .expect_normal()
+                .unwrap()
+        };
+        let error = |mat: &Array3<f32>| (mat - &src).mapv(f32::abs).mean().unwrap();
+
+        let blurred = run(DeconvType::Blur, src.clone());
+        let blurred_error = error(&blurred);
+        assert!(blurred_error > 0.01);
+        for &ty in &[
+            DeconvType::Inverse,
+            DeconvType::Wiener,
+            DeconvType::RichardsonLucy,
+        ] {
+            let restored = run(ty, blurred.clone());
+            assert!(error(&restored) < blurred_error * 0.7, "{:?}", ty);
+        }
+
+        // Zero gain is not divided by, even without a threshold.
+        let mat = Array::from_shape_fn((8, 8), |(x, y)| (x * 3 + y) as f32);
+        let otf = Array::from_shape_fn((8, 8), |(x, _)| C::from((x != 4) as u8 as f32));
+        let restored = inverse_filter(mat.clone(), &otf, 0.0, &job).unwrap();
+        for (a, b) in restored.iter().zip(&mat) {
+            assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+        }
+    }
+}
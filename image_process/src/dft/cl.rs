@@ -0,0 +1,199 @@
+use super::{FFTKind, FFT};
+use crate::{OptionExt as _, Result};
+use ndarray::prelude::*;
+use num_complex::Complex32 as C;
+use ocl::{builders::KernelBuilder, prm::Float2, Buffer, Context, Device, Kernel, Program, Queue};
+
+const OPENCL_KERNEL_SRC: &str = include_str!("./kernel.cl");
+
+pub fn dft(src: Array3<f32>) -> Result<Array3<C>> {
+    super::dft_by(src, |chans| fft_2d_batch(chans, false))
+}
+
+pub fn idft(src: Array3<C>) -> Result<Array3<f32>> {
+    super::idft_by(src, |spectra| fft_2d_batch(spectra, true))
+}
+
+/// Same as `super::fft_2d`. Sizes other than powers of two use Bluestein's algorithm.
+pub fn fft_2d(mat: Array2<C>, inverse: bool) -> Result<Array2<C>> {
+    Ok(fft_2d_batch(vec![mat], inverse)?.pop().unwrap())
+}
+
+/// Transform images in sequence, sharing the OpenCL setup.
+pub fn fft_2d_batch(mats: Vec<Array2<C>>, inverse: bool) -> Result<Vec<Array2<C>>> {
+    // Setup OpenCL
+
+    let context = Context::builder()
+        .devices(Device::specifier().first())
+        .build()?;
+    let device = *context.devices().first().context("No OpenCL device")?;
+
+    let queue = Queue::new(&context, device, None)?;
+    let program = Program::builder()
+        .src(OPENCL_KERNEL_SRC)
+        .devices(device)
+        .build(&context)?;
+    let cl = Cl { queue, program };
+
+    mats.into_iter()
+        .map(|mat| {
+            let (h, w) = mat.dim();
+            let buf: Vec<Float2> = mat.iter().map(|v| Float2::new(v.re, v.im)).collect();
+            let buf = cl.buffer(&buf)?;
+            let tmp = cl.zeros(h * w)?;
+
+            // Transform rows, then columns as rows of the transposed matrix.
+            cl.fft_rows(&buf, &tmp, h, w, inverse)?;
+            enq(cl.kernel("transpose", (w, h)).arg(&buf).arg(&tmp))?;
+            cl.fft_rows(&tmp, &buf, w, h, inverse)?;
+            enq(cl.kernel("transpose", (h, w)).arg(&tmp).arg(&buf))?;
+
+            let mut out = vec![Float2::new(0.0, 0.0); h * w];
+            buf.read(&mut out).enq()?;
+            // Kernels are unnormalized.
+            let k = 1.0 / ((h * w) as f32).sqrt();
+            let out = out.iter().map(|v| C::new(v[0], v[1]) * k).collect();
+            Ok(Array::from_shape_vec((h, w), out).unwrap())
+        })
+        .collect()
+}
+
+struct Cl {
+    queue: Queue,
+    program: Program,
+}
+
+impl Cl {
+    fn buffer(&self, data: &[Float2]) -> Result<Buffer<Float2>> {
+        Ok(Buffer::builder()
+            .queue(self.queue.clone())
+            .len(data.len())
+            .copy_host_slice(data)
+            .build()?)
+    }
+
+    fn zeros(&self, len: usize) -> Result<Buffer<Float2>> {
+        Ok(Buffer::builder()
+            .queue(self.queue.clone())
+            .len(len)
+            .fill_val(Float2::new(0.0, 0.0))
+            .build()?)
+    }
+
+    /// Builder of kernel `name` on a 2D range. Arguments are set by the caller.
+    fn kernel(&self, name: &str, size: (usize, usize)) -> KernelBuilder<'_> {
+        let mut builder = Kernel::builder();
+        builder
+            .name(name)
+            .program(&self.program)
+            .queue(self.queue.clone())
+            .global_work_size(size);
+        builder
+    }
+
+    /// Unnormalized 1D-FFT on `rows` rows of length `n` in `buf`. `tmp` has the same length.
+    fn fft_rows(
+        &self,
+        buf: &Buffer<Float2>,
+        tmp: &Buffer<Float2>,
+        rows: usize,
+        n: usize,
+        inverse: bool,
+    ) -> Result<()> {
+        let plan = FFT::cached(n);
+        let (chirp, chirp_fft, inner) = match &plan.kind {
+            FFTKind::Radix2 { .. } => return self.radix2_rows(buf, tmp, rows, n, inverse),
+            FFTKind::Bluestein {
+                chirp,
+                chirp_fft,
+                inner,
+            } => (chirp, chirp_fft, inner),
+        };
+
+        let to_cl = |v: &C| Float2::new(v.re, v.im);
+        let chirp = self.buffer(&chirp.iter().map(to_cl).collect::<Vec<_>>())?;
+        let chirp_fft = self.buffer(&chirp_fft.iter().map(to_cl).collect::<Vec<_>>())?;
+        let m = inner.size();
+        let pad = self.zeros(rows * m)?;
+        let pad_tmp = self.zeros(rows * m)?;
+        let (n_arg, m_arg, inverse_arg) = (n as i32, m as i32, inverse as i32);
+
+        enq(self
+            .kernel("chirp_pad", (m, rows))
+            .arg(buf)
+            .arg(&pad)
+            .arg(&chirp)
+            .arg(n_arg)
+            .arg(inverse_arg))?;
+        self.radix2_rows(&pad, &pad_tmp, rows, m, false)?;
+        enq(self.kernel("mul_rows", (m, rows)).arg(&pad).arg(&chirp_fft))?;
+        self.radix2_rows(&pad, &pad_tmp, rows, m, true)?;
+        enq(self
+            .kernel("chirp_unpad", (n, rows))
+            .arg(&pad)
+            .arg(buf)
+            .arg(&chirp)
+            .arg(m_arg)
+            .arg(inverse_arg))
+    }
+
+    /// Unnormalized radix-2 FFT on rows of length `n` of power of two, ping-ponging with `tmp`.
+    fn radix2_rows(
+        &self,
+        buf: &Buffer<Float2>,
+        tmp: &Buffer<Float2>,
+        rows: usize,
+        n: usize,
+        inverse: bool,
+    ) -> Result<()> {
+        let sign = if inverse { 1.0f32 } else { -1.0 };
+        let (mut src, mut dest) = (buf, tmp);
+        let mut p = 1;
+        while p < n {
+            let p_arg = p as i32;
+            enq(self
+                .kernel("fft_radix2", (n / 2, rows))
+                .arg(src)
+                .arg(dest)
+                .arg(p_arg)
+                .arg(sign))?;
+            std::mem::swap(&mut src, &mut dest);
+            p <<= 1;
+        }
+        // Odd passes leave the result in `tmp`.
+        if !std::ptr::eq(src, buf) {
+            src.copy(buf, None, None).enq()?;
+        }
+        Ok(())
+    }
+}
+
+fn enq(builder: &KernelBuilder) -> Result<()> {
+    let kernel = builder.build()?;
+    unsafe { kernel.enq()? };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::Job;
+
+    #[test]
+    #[ignore = "requires an OpenCL device"]
+    fn test_fft_2d_cl() {
+        let job = Job::default();
+        for &(h, w) in &[(8, 16), (12, 5), (1, 7)] {
+            let src = Array::from_shape_fn((h, w), |(x, y)| {
+                C::new(((x * 3 + y * 5) % 7) as f32, (x % 2) as f32)
+            });
+            for &inverse in &[false, true] {
+                let expect = super::super::fft_2d(src.clone(), inverse, &job).unwrap();
+                let dest = fft_2d(src.clone(), inverse).unwrap();
+                for (a, b) in expect.iter().zip(&dest) {
+                    assert!((a - b).norm() < 1e-3, "{}x{}: {} != {}", h, w, a, b);
+                }
+            }
+        }
+    }
+}
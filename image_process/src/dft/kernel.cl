@@ -0,0 +1,88 @@
+// Complex numbers are stored as float2 of (re, im).
+
+float2 cmul (float2 a, float2 b) {
+    return (float2)(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x);
+}
+
+// One pass of Stockham radix-2 FFT on each row of length `n`, unnormalized.
+// Global size is (n / 2, rows). `p` goes through 1, 2, 4, ..., n / 2.
+kernel void fft_radix2 (
+    global const float2 *x,
+    global float2 *y,
+    int p,
+    // -1 for the forward transform, 1 for the inverse.
+    float sign
+) {
+    int half_n = get_global_size(0);
+    int i = get_global_id(0);
+    int row = get_global_id(1) * half_n * 2;
+    int k = i & (p - 1);
+
+    float c;
+    float s = sincos(sign * M_PI_F * k / p, &c);
+    float2 u0 = x[row + i];
+    float2 u1 = cmul(x[row + i + half_n], (float2)(c, s));
+
+    int j = (i << 1) - k;
+    y[row + j] = u0 + u1;
+    y[row + j + p] = u0 - u1;
+}
+
+// Global size is (w, h) of the input.
+kernel void transpose (
+    global const float2 *x,
+    global float2 *y
+) {
+    int w = get_global_size(0), h = get_global_size(1);
+    int c = get_global_id(0), r = get_global_id(1);
+    y[c * h + r] = x[r * w + c];
+}
+
+// Bluestein's algorithm. See `FFT::transform`.
+// Multiply rows of length `n` by the chirp, and pad them with zeros to length `m`.
+// Global size is (m, rows).
+kernel void chirp_pad (
+    global const float2 *x,
+    global float2 *y,
+    global const float2 *chirp,
+    int n,
+    int inverse
+) {
+    int m = get_global_size(0);
+    int j = get_global_id(0), row = get_global_id(1);
+    float2 v = (float2)(0.f, 0.f);
+    if (j < n) {
+        v = x[row * n + j];
+        // Inverse transform is the conjugate of the forward one on conjugated input.
+        if (inverse)
+            v.y = -v.y;
+        v = cmul(v, chirp[j]);
+    }
+    y[row * m + j] = v;
+}
+
+// Multiply each row by `h` elementwise. Global size is (m, rows).
+kernel void mul_rows (
+    global float2 *x,
+    global const float2 *h
+) {
+    int m = get_global_size(0);
+    int j = get_global_id(0), row = get_global_id(1);
+    x[row * m + j] = cmul(x[row * m + j], h[j]);
+}
+
+// Inverse of `chirp_pad`. Global size is (n, rows).
+kernel void chirp_unpad (
+    global const float2 *x,
+    global float2 *y,
+    global const float2 *chirp,
+    int m,
+    int inverse
+) {
+    int n = get_global_size(0);
+    int j = get_global_id(0), row = get_global_id(1);
+    float2 v = cmul(x[row * m + j], chirp[j]) / (float)m;
+    if (inverse)
+        v.y = -v.y;
+    y[row * n + j] = v;
+}
@@ -0,0 +1,452 @@
+use crate::{
+    image::Image,
+    processor::{Job, ParamKind, ParamSpec, Params, Processor},
+    Result,
+};
+use failure::ensure;
+use ndarray::prelude::*;
+use num_complex::Complex32 as C;
+use once_cell::sync::OnceCell;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+pub mod cl;
+
+pub struct DFT;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DFTParams {
+    pub inverse: bool,
+    pub ocl: bool,
+}
+
+impl Params for DFTParams {
+    const SPECS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "inverse",
+            desc: "Run IDFT on a complex image",
+            kind: ParamKind::Bool { default: false },
+        },
+        ParamSpec {
+            name: "ocl",
+            desc: "Run with OpenCL",
+            kind: ParamKind::Bool { default: false },
+        },
+    ];
+}
+
+impl Processor for DFT {
+    const NAME: &'static str = "dft";
+    type Params = DFTParams;
+
+    fn run(&self, params: &DFTParams, src: Image, job: &Job) -> Result<Image> {
+        match (params.inverse, params.ocl) {
+            (false, false) => Ok(Image::Complex(dft(src.expect_normal()?, job)?)),
+            (false, true) => Ok(Image::Complex(cl::dft(src.expect_normal()?)?)),
+            (true, false) => Ok(Image::Normal(idft(src.expect_complex()?, job)?)),
+            (true, true) => Ok(Image::Normal(cl::idft(src.expect_complex()?)?)),
+        }
+    }
+}
+
+/// Run shifted 2D-DFT on each channel.
+pub fn dft(src: Array3<f32>, job: &Job) -> Result<Array3<C>> {
+    let (h, w, _) = src.dim();
+    dft_by(src, |chans| FFT2D::init(h, w).run_batch(chans, false, job))
+}
+
+/// Inverse of `dft`. Output the real part of each channel.
+pub fn idft(src: Array3<C>, job: &Job) -> Result<Array3<f32>> {
+    let (h, w, _) = src.dim();
+    idft_by(src, |spectra| {
+        FFT2D::init(h, w).run_batch(spectra, true, job)
+    })
+}
+
+/// `dft` with `fft` running 2D-FFT on all channels.
+fn dft_by(
+    src: Array3<f32>,
+    fft: impl FnOnce(Vec<Array2<C>>) -> Result<Vec<Array2<C>>>,
+) -> Result<Array3<C>> {
+    let chans = src.axis_iter(Axis(2)).map(|chan| chan.mapv(C::from));
+    let spectra = fft(chans.collect())?;
+    let mut dest = Array::zeros(src.dim());
+    for (col, spectrum) in spectra.iter().enumerate() {
+        dest.index_axis_mut(Axis(2), col)
+            .assign(&fft_shift(spectrum, false));
+    }
+    Ok(dest)
+}
+
+/// `idft` with `fft` running inverse 2D-FFT on all channels.
+fn idft_by(
+    src: Array3<C>,
+    fft: impl FnOnce(Vec<Array2<C>>) -> Result<Vec<Array2<C>>>,
+) -> Result<Array3<f32>> {
+    let spectra = src
+        .axis_iter(Axis(2))
+        .map(|chan| fft_shift(&chan.to_owned(), true));
+    let chans = fft(spectra.collect())?;
+    let mut dest = Array::zeros(src.dim());
+    for (col, chan) in chans.iter().enumerate() {
+        dest.index_axis_mut(Axis(2), col)
+            .assign(&chan.mapv(|v| v.re));
+    }
+    Ok(dest)
+}
+
+/// Move the zero frequency to the center, or back to the top-left corner if `inverse`.
+pub fn fft_shift(src: &Array2<C>, inverse: bool) -> Array2<C> {
+    let (h, w) = src.dim();
+    let (dx, dy) = if inverse {
+        (h - h / 2, w - w / 2)
+    } else {
+        (h / 2, w / 2)
+    };
+    Array::from_shape_fn((h, w), |(x, y)| src[[(x + h - dx) % h, (y + w - dy) % w]])
+}
+
+/// 1D-FFT of a fixed size, normalized by `1/sqrt(n)`.
+///
+/// Sizes of power of two use radix-2 FFT directly.
+/// Others are transformed as a convolution of power-of-two size (Bluestein's algorithm).
+#[derive(Debug)]
+pub struct FFT {
+    n: usize,
+    kind: FFTKind,
+}
+
+#[derive(Debug)]
+enum FFTKind {
+    Radix2 {
+        w: Vec<C>,
+        butterfly: Vec<usize>,
+    },
+    Bluestein {
+        /// e^(-iπk^2/n)
+        chirp: Vec<C>,
+        /// Unnormalized FFT of the conjugated chirp, padded and wrapped around.
+        chirp_fft: Array1<C>,
+        inner: Box<FFT>,
+    },
+}
+
+impl FFT {
+    pub fn init(n: usize) -> Self {
+        assert!(n > 0);
+        if n.is_power_of_two() {
+            return Self::init_radix2(n);
+        }
+
+        let chirp: Vec<C> = (0..n)
+            .map(|k| {
+                // k^2 modulo 2n to keep the precision for large k.
+                let t = (k * k % (2 * n)) as f64 * std::f64::consts::PI / n as f64;
+                C::new(t.cos() as f32, -t.sin() as f32)
+            })
+            .collect();
+
+        let m = (2 * n - 1).next_power_of_two();
+        let inner = Self::init_radix2(m);
+        let mut chirp_fft = Array::zeros(m);
+        chirp_fft[0] = chirp[0].conj();
+        for k in 1..n {
+            chirp_fft[k] = chirp[k].conj();
+            chirp_fft[m - k] = chirp[k].conj();
+        }
+        inner.transform(chirp_fft.view_mut(), false);
+
+        Self {
+            n,
+            kind: FFTKind::Bluestein {
+                chirp,
+                chirp_fft,
+                inner: Box::new(inner),
+            },
+        }
+    }
+
+    fn init_radix2(n: usize) -> Self {
+        let theta = -2.0 * std::f32::consts::PI / n as f32;
+        let w = (0..n)
+            .map(|i| C::from_polar(&1.0, &(theta * i as f32)))
+            .collect();
+
+        let mut butterfly = vec![0; n];
+        let mut j = 0;
+        for i in 1..n {
+            let mut k = n >> 1;
+            while j & k != 0 {
+                k >>= 1;
+            }
+            j = j & (k - 1) | k;
+            butterfly[i] = j;
+        }
+
+        Self {
+            n,
+            kind: FFTKind::Radix2 { w, butterfly },
+        }
+    }
+
+    /// Shared plan of size `n`, initialized on the first use.
+    /// Only the most recently used plans are kept, see `PlanCache`.
+    pub fn cached(n: usize) -> Arc<Self> {
+        static PLANS: OnceCell<PlanCache> = OnceCell::new();
+        PLANS.get_or_init(|| PlanCache::new(16)).get(n)
+    }
+
+    pub fn size(&self) -> usize {
+        self.n
+    }
+
+    pub fn fft(&self, mut mat: ArrayViewMut1<C>, inverse: bool) {
+        assert_eq!(mat.shape(), [self.n]);
+        self.transform(mat.view_mut(), inverse);
+        let k = (1.0 / self.n as f32).sqrt();
+        mat.iter_mut().for_each(|x| *x *= k);
+    }
+
+    /// Unnormalized transform.
+    fn transform(&self, mut mat: ArrayViewMut1<C>, inverse: bool) {
+        match &self.kind {
+            FFTKind::Radix2 { w, butterfly } => {
+                let n = self.n;
+                let logn = n.trailing_zeros() as usize;
+
+                // Butterfly swap
+                (0..n)
+                    .filter(|&i| i < butterfly[i])
+                    .for_each(|i| mat.swap([i], [butterfly[i]]));
+
+                let mut h = 1;
+                for t in (0..logn).rev() {
+                    for i in (0..n).step_by(h << 1) {
+                        for j in 0..h {
+                            let w = w[j << t];
+                            let u = mat[[i + j]];
+                            let v = mat[[i + j + h]] * if inverse { w.conj() } else { w };
+                            mat[[i + j]] = u + v;
+                            mat[[i + j + h]] = u - v;
+                        }
+                    }
+                    h <<= 1;
+                }
+            }
+            FFTKind::Bluestein {
+                chirp,
+                chirp_fft,
+                inner,
+            } => {
+                // Inverse transform is the conjugate of the forward one on conjugated input.
+                let conj = |v: C| if inverse { v.conj() } else { v };
+
+                // X[k] = chirp[k] * sum_j (x[j] * chirp[j]) * conj(chirp[k - j])
+                let mut buf = Array::zeros(inner.size());
+                for (k, &c) in chirp.iter().enumerate() {
+                    buf[k] = conj(mat[k]) * c;
+                }
+                inner.transform(buf.view_mut(), false);
+                buf *= chirp_fft;
+                inner.transform(buf.view_mut(), true);
+
+                let k = 1.0 / inner.size() as f32;
+                for (i, &c) in chirp.iter().enumerate() {
+                    mat[i] = conj(buf[i] * c * k);
+                }
+            }
+        }
+    }
+}
+
+/// Run 2D-FFT on the whole image. Any size is supported.
+pub fn fft_2d(mat: Array2<C>, inverse: bool, job: &Job) -> Result<Array2<C>> {
+    let (h, w) = mat.dim();
+    FFT2D::init(h, w).run(mat, inverse, job)
+}
+
+/// Plans of the least recently used sizes are dropped beyond `capacity`.
+struct PlanCache {
+    capacity: usize,
+    /// The most recently used first.
+    plans: Mutex<VecDeque<Arc<FFT>>>,
+}
+
+impl PlanCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            plans: Mutex::new(VecDeque::with_capacity(capacity + 1)),
+        }
+    }
+
+    fn get(&self, n: usize) -> Arc<FFT> {
+        if let Some(plan) = self.touch(n, None) {
+            return plan;
+        }
+        // Initialize outside the lock, so that other sizes are not blocked.
+        let plan = Arc::new(FFT::init(n));
+        self.touch(n, Some(plan)).unwrap()
+    }
+
+    /// Move the plan of size `n` to the front, or insert `new` there if it is absent.
+    fn touch(&self, n: usize, new: Option<Arc<FFT>>) -> Option<Arc<FFT>> {
+        let mut plans = self.plans.lock().unwrap();
+        let plan = match plans.iter().position(|p| p.n == n) {
+            Some(pos) => plans.remove(pos).unwrap(),
+            None => new?,
+        };
+        plans.push_front(plan.clone());
+        plans.truncate(self.capacity);
+        Some(plan)
+    }
+}
+
+/// 2D-FFT of a fixed size, to transform many images without replanning.
+#[derive(Debug, Clone)]
+pub struct FFT2D {
+    /// Plan for columns, of the height.
+    f1: Arc<FFT>,
+    /// Plan for rows, of the width.
+    f2: Arc<FFT>,
+}
+
+impl FFT2D {
+    /// Plans are taken from `FFT::cached`.
+    pub fn init(h: usize, w: usize) -> Self {
+        Self {
+            f1: FFT::cached(h),
+            f2: FFT::cached(w),
+        }
+    }
+
+    pub fn dim(&self) -> (usize, usize) {
+        (self.f1.size(), self.f2.size())
+    }
+
+    pub fn run(&self, mat: Array2<C>, inverse: bool, job: &Job) -> Result<Array2<C>> {
+        Ok(self.run_batch(vec![mat], inverse, job)?.pop().unwrap())
+    }
+
+    /// Transform images of the same size in parallel.
+    pub fn run_batch(
+        &self,
+        mut mats: Vec<Array2<C>>,
+        inverse: bool,
+        job: &Job,
+    ) -> Result<Vec<Array2<C>>> {
+        let (n, m) = self.dim();
+        for mat in &mats {
+            ensure!(
+                mat.dim() == (n, m),
+                "Size {:?} mismatches the plan of {:?}",
+                mat.dim(),
+                (n, m),
+            );
+        }
+        let progress = job.progress(mats.len() * (n + m));
+        // Run 1D-FFT on each lane along `axis`.
+        let run = |mat: &mut Array2<C>, axis: usize, f: &FFT| {
+            mat.axis_iter_mut(Axis(1 - axis))
+                .into_par_iter()
+                .for_each(|mut lane| {
+                    if !job.is_cancelled() {
+                        f.fft(lane.view_mut(), inverse);
+                        progress.tick();
+                    }
+                });
+        };
+
+        mats.par_iter_mut().for_each(|mat| {
+            if !inverse {
+                // Run 1D-FFT for each row and then for each column.
+                run(mat, 1, &self.f2);
+                run(mat, 0, &self.f1);
+            } else {
+                // Run in inverse order when running inverse-FFT.
+                run(mat, 0, &self.f1);
+                run(mat, 1, &self.f2);
+            }
+        });
+        job.check()?;
+        Ok(mats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fft_any_size() {
+        for &n in &[1, 2, 3, 5, 8, 12, 17, 100] {
+            let src = Array::from_shape_fn(n, |i| C::new((i * 7 % 5) as f32, (i % 3) as f32 - 1.0));
+            let mut dest = src.clone();
+            FFT::init(n).fft(dest.view_mut(), false);
+            for k in 0..n {
+                let expect: C = (0..n)
+                    .map(|j| {
+                        let t = -2.0 * std::f32::consts::PI * (j * k % n) as f32 / n as f32;
+                        src[j] * C::from_polar(&1.0, &t)
+                    })
+                    .sum::<C>()
+                    / (n as f32).sqrt();
+                assert!((dest[k] - expect).norm() < 1e-3, "n = {}, k = {}", n, k);
+            }
+
+            FFT::init(n).fft(dest.view_mut(), true);
+            for (a, b) in src.iter().zip(dest.iter()) {
+                assert!((a - b).norm() < 1e-3, "n = {}", n);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fft_batch() {
+        let job = Job::default();
+        let mats: Vec<_> = (0..3)
+            .map(|i| Array::from_shape_fn((6, 10), |(x, y)| C::new((x * y + i) as f32, i as f32)))
+            .collect();
+        let plan = FFT2D::init(6, 10);
+        let batch = plan.run_batch(mats.clone(), false, &job).unwrap();
+        for (mat, spectrum) in mats.into_iter().zip(&batch) {
+            let expect = plan.run(mat, false, &job).unwrap();
+            assert!(expect
+                .iter()
+                .zip(spectrum)
+                .all(|(a, b)| (a - b).norm() < 1e-4));
+        }
+        assert!(plan.run(Array::zeros((10, 6)), false, &job).is_err());
+    }
+
+    #[test]
+    fn test_plan_cache() {
+        let cache = PlanCache::new(2);
+        let plan = cache.get(12);
+        assert!(Arc::ptr_eq(&plan, &cache.get(12)));
+        cache.get(5);
+        // 12 is used more recently than 5.
+        cache.get(12);
+        cache.get(7);
+        assert!(Arc::ptr_eq(&plan, &cache.get(12)));
+        let sizes: Vec<_> = cache.plans.lock().unwrap().iter().map(|p| p.n).collect();
+        assert_eq!(sizes, [12, 7]);
+    }
+
+    #[test]
+    fn test_dft_round_trip() {
+        let job = Job::default();
+        let src = Array::from_shape_fn((9, 14, 3), |(x, y, col)| {
+            (x * 14 + y) as f32 / 126.0 * (col + 1) as f32
+        });
+        let dest = idft(dft(src.clone(), &job).unwrap(), &job).unwrap();
+        assert_eq!(dest.dim(), src.dim());
+        for (a, b) in src.iter().zip(dest.iter()) {
+            assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{
+    filter::{linear_filter, pad_border, separable_filter, Border},
+    image::{to_gray, Image},
+    processor::{Job, ParamKind, ParamSpec, Params, Processor},
+    Result,
+};
+use failure::ensure;
+use ndarray::{prelude::*, Zip};
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// Edge detection on the luma. The output is a grayscale image.
+pub struct Edge;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeType {
+    Sobel,
+    Prewitt,
+    Scharr,
+    /// Zero crossings of the Laplacian of Gaussian (Marr-Hildreth).
+    #[serde(rename = "log")]
+    LaplacianOfGaussian,
+    Canny,
+}
+
+/// Output of gradient operators. LoG and Canny always output binary edges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeOutput {
+    Magnitude,
+    /// Direction counterclockwise from the horizontal, mapping [-π, π] to [0, 1].
+    Direction,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EdgeParams {
+    #[serde(rename = "type")]
+    pub ty: EdgeType,
+    pub output: EdgeOutput,
+    /// Sigma of the Gaussian smoothing of LoG and Canny.
+    pub sigma: f32,
+    /// Lower threshold of Canny, or the minimal contrast of zero crossings of LoG.
+    pub low: f32,
+    /// Higher threshold of Canny.
+    pub high: f32,
+}
+
+impl Params for EdgeParams {
+    const SPECS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "type",
+            desc: "Edge operator",
+            kind: ParamKind::Choice {
+                choices: &["sobel", "prewitt", "scharr", "log", "canny"],
+                default: "sobel",
+            },
+        },
+        ParamSpec {
+            name: "output",
+            desc: "Output of gradient operators",
+            kind: ParamKind::Choice {
+                choices: &["magnitude", "direction"],
+                default: "magnitude",
+            },
+        },
+        ParamSpec {
+            name: "sigma",
+            desc: "Sigma of Gaussian smoothing of LoG and Canny",
+            kind: ParamKind::Float {
+                min: 0.3,
+                max: 20.0,
+                default: 1.4,
+            },
+        },
+        ParamSpec {
+            name: "low",
+            desc: "Lower gradient threshold of Canny, or minimal contrast of zero crossings of LoG",
+            kind: ParamKind::Float {
+                min: 0.0,
+                max: 1.0,
+                default: 0.03,
+            },
+        },
+        ParamSpec {
+            name: "high",
+            desc: "Higher gradient threshold of Canny",
+            kind: ParamKind::Float {
+                min: 0.0,
+                max: 1.0,
+                default: 0.08,
+            },
+        },
+    ];
+
+    fn check(&self) -> Result<()> {
+        ensure!(
+            self.low <= self.high,
+            "Lower threshold should not exceed the higher one",
+        );
+        Ok(())
+    }
+}
+
+impl Processor for Edge {
+    const NAME: &'static str = "edge";
+    type Params = EdgeParams;
+
+    fn run(&self, params: &EdgeParams, src: Image, job: &Job) -> Result<Image> {
+        let src = to_gray(&src.expect_normal()?);
+        let binary = |edges: Array2<bool>| edges.mapv(|v| v as u8 as f32);
+        let dest = match params.ty {
+            EdgeType::LaplacianOfGaussian => binary(zero_crossings(
+                &laplacian_of_gaussian(&src, params.sigma, job)?,
+                params.low,
+            )),
+            EdgeType::Canny => binary(canny(&src, params.sigma, params.low, params.high, job)?),
+            ty => {
+                let (dx, dy) = gradient(&src, ty, job)?;
+                Array::from_shape_fn(dx.dim(), |i| match params.output {
+                    EdgeOutput::Magnitude => dx[i].hypot(dy[i]),
+                    // Rows go downwards.
+                    EdgeOutput::Direction => ((-dx[i]).atan2(dy[i]) + PI) / (2.0 * PI),
+                })
+            }
+        };
+        Ok(Image::Normal(dest.insert_axis(Axis(2))))
+    }
+}
+
+/// Smoothing and derivative factors of 3x3 gradient operators, scaled so that the gradient of
+/// a linear ramp is its slope.
+pub fn gradient_kernels(ty: EdgeType) -> (Array1<f32>, Array1<f32>) {
+    let smooth = match ty {
+        EdgeType::Sobel => arr1(&[1.0, 2.0, 1.0]) / 4.0,
+        EdgeType::Prewitt => arr1(&[1.0, 1.0, 1.0]) / 3.0,
+        EdgeType::Scharr => arr1(&[3.0, 10.0, 3.0]) / 16.0,
+        _ => panic!("{:?} is not a gradient operator", ty),
+    };
+    (smooth, arr1(&[-0.5, 0.0, 0.5]))
+}
+
+/// Derivatives along rows and columns by a 3x3 operator, keeping the size.
+pub fn gradient(src: &Array2<f32>, ty: EdgeType, job: &Job) -> Result<(Array2<f32>, Array2<f32>)> {
+    let (smooth, diff) = gradient_kernels(ty);
+    // Reflection gives no gradient across borders.
+    let src = pad_border(&src.clone().insert_axis(Axis(2)), 1, Border::Reflect, 0.0);
+    let dx = separable_filter(src.clone(), &diff, &smooth, &job.part(0, 2))?;
+    let dy = separable_filter(src, &smooth, &diff, &job.part(1, 2))?;
+    Ok((
+        dx.index_axis_move(Axis(2), 0),
+        dy.index_axis_move(Axis(2), 0),
+    ))
+}
+
+/// Normalized 1D Gaussian with standard deviation `sigma`, truncated at 3 sigma.
+fn gauss_kernel(sigma: f32) -> Array1<f32> {
+    let mid = (3.0 * sigma).ceil() as usize;
+    let kernel = Array::from_shape_fn(2 * mid + 1, |x| {
+        let x = x as f32 - mid as f32;
+        (-x * x / (2.0 * sigma * sigma)).exp()
+    });
+    let sum = kernel.sum();
+    kernel / sum
+}
+
+/// Scale-normalized Laplacian of Gaussian `σ²∇²G`, keeping the size.
+pub fn laplacian_of_gaussian(src: &Array2<f32>, sigma: f32, job: &Job) -> Result<Array2<f32>> {
+    let mid = (3.0 * sigma).ceil() as usize;
+    let s2 = sigma * sigma;
+    let mut kernel = Array::from_shape_fn((2 * mid + 1, 2 * mid + 1), |(x, y)| {
+        let (x, y) = (x as f32 - mid as f32, y as f32 - mid as f32);
+        let r2 = (x * x + y * y) / (2.0 * s2);
+        (r2 - 1.0) * (-r2).exp() / (PI * s2)
+    });
+    // No response on flat regions despite the truncation.
+    kernel -= kernel.mean().unwrap();
+    let src = pad_border(&src.clone().insert_axis(Axis(2)), mid, Border::Reflect, 0.0);
+    Ok(linear_filter(src, kernel, job)?.index_axis_move(Axis(2), 0))
+}
+
+/// Positive pixels next to a negative one, with a difference larger than `threshold`.
+pub fn zero_crossings(src: &Array2<f32>, threshold: f32) -> Array2<bool> {
+    // Rounding errors on flat regions are not positive.
+    const EPS: f32 = 1e-6;
+    let (h, w) = src.dim();
+    Array::from_shape_fn((h, w), |(x, y)| {
+        let v = src[[x, y]];
+        v > EPS
+            && [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().any(|&(dx, dy)| {
+                let (x, y) = (x as isize + dx, y as isize + dy);
+                if x < 0 || y < 0 || x >= h as isize || y >= w as isize {
+                    return false;
+                }
+                let u = src[[x as usize, y as usize]];
+                u < -EPS && v - u > threshold
+            })
+    })
+}
+
+/// Canny edge detector: Gaussian smoothing, Sobel gradient, non-maximum suppression along the
+/// gradient, and hysteresis tracking of weak edges connected to strong ones.
+pub fn canny(
+    src: &Array2<f32>,
+    sigma: f32,
+    low: f32,
+    high: f32,
+    job: &Job,
+) -> Result<Array2<bool>> {
+    let (h, w) = src.dim();
+    let kernel = gauss_kernel(sigma);
+    let mid = kernel.len() / 2;
+    let padded = pad_border(&src.clone().insert_axis(Axis(2)), mid, Border::Reflect, 0.0);
+    let smoothed = separable_filter(padded, &kernel, &kernel, &job.part(0, 2))?;
+    let (dx, dy) = gradient(
+        &smoothed.index_axis_move(Axis(2), 0),
+        EdgeType::Sobel,
+        &job.part(1, 2),
+    )?;
+    let mag = Array::from_shape_fn((h, w), |i| dx[i].hypot(dy[i]));
+
+    // 0 for none, 1 for weak and 2 for strong edges.
+    let mut state = Array2::<u8>::zeros((h, w));
+    Zip::indexed(&mut state).par_apply(|(x, y), s| {
+        let m = mag[[x, y]];
+        if m < low || m == 0.0 {
+            return;
+        }
+        // Neighbors along the gradient, quantized to 4 directions.
+        let angle = dx[[x, y]].atan2(dy[[x, y]]).to_degrees().rem_euclid(180.0);
+        let (ox, oy) = [(0, 1), (1, 1), (1, 0), (1, -1)][(angle / 45.0).round() as usize % 4];
+        let at = |d: isize| {
+            let (x, y) = (x as isize + d * ox, y as isize + d * oy);
+            if 0 <= x && 0 <= y {
+                mag.get((x as usize, y as usize)).copied().unwrap_or(0.0)
+            } else {
+                0.0
+            }
+        };
+        // Ties are broken to keep edges one pixel wide.
+        if m > at(1) && m >= at(-1) {
+            *s = if m >= high { 2 } else { 1 };
+        }
+    });
+
+    let mut edges = state.mapv(|s| s == 2);
+    let mut stack: Vec<_> = edges
+        .indexed_iter()
+        .filter(|(_, &e)| e)
+        .map(|(idx, _)| idx)
+        .collect();
+    while let Some((x, y)) = stack.pop() {
+        for nx in x.saturating_sub(1)..(x + 2).min(h) {
+            for ny in y.saturating_sub(1)..(y + 2).min(w) {
+                if state[[nx, ny]] == 1 && !edges[[nx, ny]] {
+                    edges[[nx, ny]] = true;
+                    stack.push((nx, ny));
+                }
+            }
+        }
+    }
+    Ok(edges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_edge() {
+        let job = Job::default();
+        // A vertical step between columns 7 and 8.
+        let src = Array::from_shape_fn((16, 16, 1), |(_, y, _)| (y >= 8) as u8 as f32);
+        let run = |ty, output| {
+            let params = EdgeParams {
+                ty,
+                output,
+                sigma: 1.0,
+                ..EdgeParams::defaults()
+            };
+            let dest = Edge
+                .run(&params, Image::Normal(src.clone()), &job)
+                .unwrap()
+                .expect_normal()
+                .unwrap();
+            assert_eq!(dest.dim(), src.dim());
+            dest.index_axis_move(Axis(2), 0)
+        };
+
+        for &ty in &[EdgeType::Sobel, EdgeType::Prewitt, EdgeType::Scharr] {
+            let mag = run(ty, EdgeOutput::Magnitude);
+            assert!(mag.column(7).iter().all(|&v| (v - 0.5).abs() < 1e-6));
+            assert!(mag.column(3).iter().all(|&v| v == 0.0));
+            // Pointing right.
+            let dir = run(ty, EdgeOutput::Direction);
+            assert!((dir[[5, 8]] - 0.5).abs() < 1e-6);
+        }
+        for &ty in &[EdgeType::LaplacianOfGaussian, EdgeType::Canny] {
+            let edges = run(ty, EdgeOutput::Magnitude);
+            for row in edges.genrows() {
+                assert_eq!(row.sum(), 1.0, "{:?}", ty);
+                assert!(row[7] == 1.0 || row[8] == 1.0, "{:?}", ty);
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use ndarray::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// How pixels out of the image are extrapolated, to keep the output size of filters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Border {
+    /// Mirror at the edge pixel, which is not repeated, eg. `cb|abcd|cb`.
+    Reflect,
+    /// Repeat the edge pixel, eg. `aa|abcd|dd`.
+    Replicate,
+    /// Tile the image periodically, eg. `cd|abcd|ab`.
+    Wrap,
+    /// Fill with a constant value.
+    Constant,
+}
+
+impl Border {
+    /// Index in `0..n` which `i` is extrapolated from, or `None` for a constant.
+    pub fn index(self, i: isize, n: usize) -> Option<usize> {
+        let n = n as isize;
+        if 0 <= i && i < n {
+            return Some(i as usize);
+        }
+        let i = match self {
+            Border::Reflect if n == 1 => 0,
+            Border::Reflect => {
+                let period = 2 * (n - 1);
+                let i = i.rem_euclid(period);
+                if i < n {
+                    i
+                } else {
+                    period - i
+                }
+            }
+            Border::Replicate if i < 0 => 0,
+            Border::Replicate => n - 1,
+            Border::Wrap => i.rem_euclid(n),
+            Border::Constant => return None,
+        };
+        Some(i as usize)
+    }
+}
+
+/// Extend each channel by `radius` pixels on every side.
+/// `value` is used for `Border::Constant`.
+pub fn pad_border(src: &Array3<f32>, radius: usize, border: Border, value: f32) -> Array3<f32> {
+    let (h, w, ncol) = src.dim();
+    let r = radius as isize;
+    Array::from_shape_fn((h + 2 * radius, w + 2 * radius, ncol), |(x, y, col)| {
+        let x = border.index(x as isize - r, h);
+        let y = border.index(y as isize - r, w);
+        match (x, y) {
+            (Some(x), Some(y)) => src[[x, y, col]],
+            _ => value,
+        }
+    })
+}
+
+/// Extend each channel for a kernel of `(kh, kw)` anchored at `(kh / 2, kw / 2)`, so that
+/// the output of `linear_filter` has the original size.
+pub fn pad_for_kernel(
+    src: &Array3<f32>,
+    (kh, kw): (usize, usize),
+    border: Border,
+    value: f32,
+) -> Array3<f32> {
+    let (h, w, _) = src.dim();
+    let radius = kh.max(kw) / 2;
+    let (x0, y0) = (radius - kh / 2, radius - kw / 2);
+    pad_border(src, radius, border, value)
+        .slice(s![x0..x0 + h + kh - 1, y0..y0 + w + kw - 1, ..])
+        .to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pad_border() {
+        let src = Array::from_shape_fn((1, 4, 1), |(_, y, _)| y as f32);
+        let row = |border| {
+            let dest = pad_border(&src, 3, border, 9.0);
+            assert_eq!(dest.dim(), (7, 10, 1));
+            dest.slice(s![3, .., 0]).to_vec()
+        };
+        let v = |s: &[u8]| s.iter().map(|&x| x as f32).collect::<Vec<_>>();
+        assert_eq!(row(Border::Reflect), v(&[3, 2, 1, 0, 1, 2, 3, 2, 1, 0]));
+        assert_eq!(row(Border::Replicate), v(&[0, 0, 0, 0, 1, 2, 3, 3, 3, 3]));
+        assert_eq!(row(Border::Wrap), v(&[1, 2, 3, 0, 1, 2, 3, 0, 1, 2]));
+        assert_eq!(row(Border::Constant), v(&[9, 9, 9, 0, 1, 2, 3, 9, 9, 9]));
+        // Rows of a single pixel.
+        let dest = pad_border(&src, 2, Border::Reflect, 0.0);
+        assert!(dest.slice(s![.., 2, 0]).iter().all(|&v| v == 0.0));
+    }
+}
@@ -1,27 +1,29 @@
-use crate::util::{OptionExt as _, Result};
+use crate::{OptionExt as _, Result};
 use ndarray::prelude::*;
 
 const OPENCL_KERNEL_SRC: &str = include_str!("./kernel.cl");
 
+/// Same as `super::linear_filter`. Output is shrunk by the kernel size minus 1.
 pub fn linear_filter(src: Array3<f32>, kernel: Array2<f32>) -> Result<Array3<f32>> {
     use ocl::{
         enums::{ImageChannelDataType, ImageChannelOrder, MemObjectType},
         Context, Device, Image, Kernel, Program, Queue,
     };
 
-    let (h, w, _) = src.dim();
-    let (ksize, ksize_) = kernel.dim();
-    assert_eq!(ksize, ksize_);
+    let (h, w, ncol) = src.dim();
+    let (kh, kw) = kernel.dim();
+    assert!(0 < kh && kh <= h && 0 < kw && kw <= w);
+    let (h2, w2) = (h - kh + 1, w - kw + 1);
 
     let kernel_buf = kernel.into_raw_vec();
 
     // Most OpenCL implementations support only f32-RGBA image,
     // so we need to expand it first.
     let src_rgba_buf = Array::from_shape_fn((h, w, 4), |(x, y, col)| {
-        if col != 3 {
+        if col < ncol {
             src[[x, y, col]]
         } else {
-            // Alpha
+            // Padding, dropped after processing.
             1.0
         }
     })
@@ -45,7 +47,7 @@ pub fn linear_filter(src: Array3<f32>, kernel: Array2<f32>) -> Result<Array3<f32
         .channel_order(ImageChannelOrder::Intensity)
         .channel_data_type(ImageChannelDataType::Float)
         .image_type(MemObjectType::Image2d)
-        .dims((ksize, ksize))
+        .dims((kw, kh))
         .flags(ocl::flags::MEM_READ_ONLY | ocl::flags::MEM_HOST_WRITE_ONLY)
         .copy_host_slice(&kernel_buf)
         .queue(queue.clone())
@@ -65,7 +67,7 @@ pub fn linear_filter(src: Array3<f32>, kernel: Array2<f32>) -> Result<Array3<f32
         .channel_order(ImageChannelOrder::Rgba)
         .channel_data_type(ImageChannelDataType::Float)
         .image_type(MemObjectType::Image2d)
-        .dims((w, h))
+        .dims((w2, h2))
         .flags(ocl::flags::MEM_WRITE_ONLY | ocl::flags::MEM_HOST_READ_ONLY)
         .queue(queue.clone())
         .build()?;
@@ -74,7 +76,7 @@ pub fn linear_filter(src: Array3<f32>, kernel: Array2<f32>) -> Result<Array3<f32
         .name("linear_transform")
         .program(&program)
         .queue(queue.clone())
-        .global_work_size((w, h))
+        .global_work_size((w2, h2))
         .arg(&kernel_image)
         .arg(&src_image)
         .arg(&dest_image)
@@ -83,9 +85,34 @@ pub fn linear_filter(src: Array3<f32>, kernel: Array2<f32>) -> Result<Array3<f32
     unsafe { kernel.enq()? };
 
     // RGBA output
-    let mut buf = vec![0.0f32; h * w * 4];
+    let mut buf = vec![0.0f32; h2 * w2 * 4];
     dest_image.read(&mut buf[..]).enq()?;
-    // Convert back to RGB
-    let dest_rgba = Array::from_shape_vec((h, w, 4), buf).unwrap();
-    Ok(dest_rgba.slice(s![.., .., ..3]).to_owned())
+    // Convert back to the original channels
+    let dest_rgba = Array::from_shape_vec((h2, w2, 4), buf).unwrap();
+    Ok(dest_rgba.slice(s![.., .., ..ncol]).to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::Job;
+
+    #[test]
+    #[ignore = "requires an OpenCL device"]
+    fn test_custom_kernel_cl() {
+        let job = Job::default();
+        let src = Array::from_shape_fn((20, 27, 2), |(x, y, col)| {
+            ((x * 7 + y * 13 + col * 5) % 11) as f32 / 11.0
+        });
+        for &(kh, kw) in &[(9, 9), (9, 6), (2, 3), (1, 4)] {
+            let kernel = Array::from_shape_fn((kh, kw), |(x, y)| (x as f32 - y as f32 * 0.5).sin());
+            let expect =
+                super::super::linear_filter_direct(src.clone(), kernel.clone(), &job).unwrap();
+            let dest = linear_filter(src.clone(), kernel).unwrap();
+            assert_eq!(expect.dim(), dest.dim());
+            for (a, b) in expect.iter().zip(&dest) {
+                assert!((a - b).abs() < 1e-4, "{}x{}: {} != {}", kh, kw, a, b);
+            }
+        }
+    }
 }
@@ -11,14 +11,15 @@ kernel void linear_transform (
 ) {
     // Axis in OpenCL is flipped.
     int2 coord = (int2)(get_global_id(1), get_global_id(0));
-    // Kernel is square.
-    int kernel_radius = get_image_height(knrl) / 2;
+    int kernel_height = get_image_height(knrl);
+    int kernel_width = get_image_width(knrl);
 
+    // Output is shrunk, so every pixel read is in the image.
     float4 sum = (float4)(0.f, 0.f, 0.f, 0.f);
-    for (int i = -kernel_radius; i < kernel_radius; ++i)
-        for (int j = -kernel_radius; j < kernel_radius; ++j) {
+    for (int i = 0; i < kernel_height; ++i)
+        for (int j = 0; j < kernel_width; ++j) {
             float4 v = read_imagef(img, sampler_const, (coord + (int2)(i, j)).yx);
-            float4 w = read_imagef(knrl, sampler_const, (kernel_radius + (int2)(i, j)).yx);
+            float4 w = read_imagef(knrl, sampler_const, (int2)(i, j).yx);
             sum += v * w;
         }
 
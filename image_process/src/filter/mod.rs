@@ -0,0 +1,646 @@
+use crate::{
+    dft::fft_2d,
+    image::Image,
+    processor::{Job, ParamKind, ParamSpec, Params, Processor},
+    Result,
+};
+use failure::{ensure, format_err};
+use ndarray::{prelude::*, Zip};
+use num_complex::Complex32 as C;
+use serde::{Deserialize, Serialize};
+
+pub use border::{pad_border, pad_for_kernel, Border};
+pub use rank::{adaptive_median_filter, extremum_filter, median_filter, rank_filter};
+
+mod border;
+pub mod cl;
+mod rank;
+
+pub struct Filter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterType {
+    Box,
+    #[serde(rename = "gauss")]
+    Gaussian,
+    #[serde(rename = "gauss_ocl")]
+    GaussianCL,
+    Wiener,
+    Bilateral,
+    Median,
+    Min,
+    Max,
+    Percentile,
+    AdaptiveMedian,
+    /// Correlate with the kernel parsed from `kernel`.
+    Custom,
+    #[serde(rename = "custom_ocl")]
+    CustomCL,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FilterParams {
+    #[serde(rename = "type")]
+    pub ty: FilterType,
+    pub neighbor: usize,
+    /// Sigma of the gaussian filter.
+    pub sigma: f32,
+    /// Sigma of the spatial weight of the bilateral filter.
+    pub sigma_d: f32,
+    /// Sigma of the range weight of the bilateral filter.
+    pub sigma_r: f32,
+    /// Percentile in [0, 100] of the percentile filter.
+    pub percentile: f32,
+    pub border: Border,
+    /// Value of pixels out of the image for `Border::Constant`.
+    pub border_value: f32,
+    /// Matrix of the custom kernel, see `parse_matrix` for the format.
+    pub kernel: String,
+    /// Scale the custom kernel to sum 1, unless it sums to 0.
+    pub normalize: bool,
+}
+
+impl Params for FilterParams {
+    const SPECS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "type",
+            desc: "Filter type",
+            kind: ParamKind::Choice {
+                choices: &[
+                    "box",
+                    "gauss",
+                    "gauss_ocl",
+                    "wiener",
+                    "bilateral",
+                    "median",
+                    "min",
+                    "max",
+                    "percentile",
+                    "adaptive_median",
+                    "custom",
+                    "custom_ocl",
+                ],
+                default: "box",
+            },
+        },
+        ParamSpec {
+            name: "neighbor",
+            desc: "Kernel size, or the maximum one of adaptive median filter, should be odd",
+            kind: ParamKind::Int {
+                min: 1,
+                max: 255,
+                default: 7,
+            },
+        },
+        ParamSpec {
+            name: "sigma",
+            desc: "Sigma of gaussian filter",
+            kind: ParamKind::Float {
+                min: 0.01,
+                max: 100.0,
+                default: 1.0,
+            },
+        },
+        ParamSpec {
+            name: "sigma_d",
+            desc: "Spatial sigma of bilateral filter",
+            kind: ParamKind::Float {
+                min: 0.01,
+                max: 100.0,
+                default: 10.0,
+            },
+        },
+        ParamSpec {
+            name: "sigma_r",
+            desc: "Range sigma of bilateral filter",
+            kind: ParamKind::Float {
+                min: 0.01,
+                max: 100.0,
+                default: 10.0,
+            },
+        },
+        ParamSpec {
+            name: "percentile",
+            desc: "Percentile of percentile filter, 50 for median",
+            kind: ParamKind::Float {
+                min: 0.0,
+                max: 100.0,
+                default: 50.0,
+            },
+        },
+        ParamSpec {
+            name: "border",
+            desc: "Extrapolation of pixels out of the image, to keep the size",
+            kind: ParamKind::Choice {
+                choices: &["reflect", "replicate", "wrap", "constant"],
+                default: "reflect",
+            },
+        },
+        ParamSpec {
+            name: "border_value",
+            desc: "Value of pixels out of the image for constant border",
+            kind: ParamKind::Float {
+                min: 0.0,
+                max: 1.0,
+                default: 0.0,
+            },
+        },
+        ParamSpec {
+            name: "kernel",
+            desc: "Custom kernel, eg. `0 -1 0; -1 5 -1; 0 -1 0`, any size, centered at the middle",
+            kind: ParamKind::Text {
+                default: "0 -1 0; -1 5 -1; 0 -1 0",
+            },
+        },
+        ParamSpec {
+            name: "normalize",
+            desc: "Scale the custom kernel to sum 1, unless it sums to 0",
+            kind: ParamKind::Bool { default: false },
+        },
+    ];
+
+    fn check(&self) -> Result<()> {
+        ensure!(self.neighbor % 2 == 1, "Kernel size should be odd number");
+        if let FilterType::Custom | FilterType::CustomCL = self.ty {
+            self.custom_kernel()?;
+        }
+        Ok(())
+    }
+}
+
+impl FilterParams {
+    pub fn custom_kernel(&self) -> Result<Array2<f32>> {
+        let mut kernel = parse_matrix(&self.kernel)?;
+        let sum = kernel.sum();
+        if self.normalize && sum.abs() > 1e-6 {
+            kernel /= sum;
+        }
+        Ok(kernel)
+    }
+}
+
+impl Processor for Filter {
+    const NAME: &'static str = "filter";
+    type Params = FilterParams;
+
+    fn run(&self, params: &FilterParams, src: Image, job: &Job) -> Result<Image> {
+        let &FilterParams {
+            ty,
+            neighbor,
+            sigma,
+            sigma_d,
+            sigma_r,
+            percentile,
+            border,
+            border_value,
+            ..
+        } = params;
+        let src = src.expect_normal()?;
+        if let FilterType::Custom | FilterType::CustomCL = ty {
+            let kernel = params.custom_kernel()?;
+            let src = pad_for_kernel(&src, kernel.dim(), border, border_value);
+            let dest = match ty {
+                FilterType::Custom => linear_filter(src, kernel, job)?,
+                _ => cl::linear_filter(src, kernel)?,
+            };
+            return Ok(Image::Normal(dest));
+        }
+        let src = pad_border(&src, neighbor / 2, border, border_value);
+
+        let dest = match ty {
+            FilterType::Box => box_filter(src, neighbor, job)?,
+            FilterType::Gaussian => {
+                let kernel = gauss_kernel_1d(neighbor, sigma);
+                separable_filter(src, &kernel, &kernel, job)?
+            }
+            FilterType::GaussianCL => cl::linear_filter(src, gauss_filter_kernel(neighbor, sigma))?,
+            FilterType::Wiener => wiener_filter(src, neighbor, job)?,
+            FilterType::Bilateral => bilateral_filter(src, neighbor, sigma_d, sigma_r, job)?,
+            FilterType::Median => median_filter(src, neighbor, job)?,
+            FilterType::Min => extremum_filter(src, neighbor, false, job)?,
+            FilterType::Max => extremum_filter(src, neighbor, true, job)?,
+            FilterType::Percentile => rank_filter(src, neighbor, percentile, job)?,
+            FilterType::AdaptiveMedian => adaptive_median_filter(src, neighbor, job)?,
+            FilterType::Custom | FilterType::CustomCL => unreachable!(),
+        };
+        Ok(Image::Normal(dest))
+    }
+}
+
+/// Kernel:
+/// K(x, y) = A * 1
+pub fn box_filter_kernel(kernel_size: usize) -> Array2<f32> {
+    // Normalize factor.
+    let k = 1.0 / kernel_size.pow(2) as f32;
+    Array::from_elem((kernel_size, kernel_size), k)
+}
+
+/// Kernel:
+/// G(x, y) = A e^((-x^2-y^2)/σ^2)
+pub fn gauss_filter_kernel(kernel_size: usize, sigma: f32) -> Array2<f32> {
+    let mid = (kernel_size / 2) as f32;
+    let mut kernel = Array::from_shape_fn((kernel_size, kernel_size), |(x, y)| {
+        let (x, y) = (x as f32, y as f32);
+        ((-(x - mid).powi(2) - (y - mid).powi(2)) / sigma.powi(2)).exp()
+    });
+    // Normalize.
+    kernel /= kernel.sum();
+    kernel
+}
+
+/// 1D factor of `gauss_filter_kernel`, which is the outer product of it with itself.
+pub fn gauss_kernel_1d(kernel_size: usize, sigma: f32) -> Array1<f32> {
+    let mid = (kernel_size / 2) as f32;
+    let kernel = Array::from_shape_fn(kernel_size, |x| {
+        (-(x as f32 - mid).powi(2) / sigma.powi(2)).exp()
+    });
+    let sum = kernel.sum();
+    kernel / sum
+}
+
+/// Parse a matrix with rows separated by newlines or `;`, and values by spaces or `,`.
+pub fn parse_matrix(text: &str) -> Result<Array2<f32>> {
+    let mut rows = Vec::new();
+    for row in text.split(&['\n', ';'][..]) {
+        let row = row
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse().map_err(|_| format_err!("Invalid number `{}`", s)))
+            .collect::<Result<Vec<f32>>>()?;
+        if !row.is_empty() {
+            rows.push(row);
+        }
+    }
+    ensure!(!rows.is_empty(), "Matrix is empty");
+    let w = rows[0].len();
+    ensure!(
+        rows.iter().all(|row| row.len() == w),
+        "Rows should have the same length",
+    );
+    Ok(Array::from_shape_fn((rows.len(), w), |(x, y)| rows[x][y]))
+}
+
+/// Kernels with at least as many entries as a square of this size are convolved via FFT in
+/// `linear_filter`.
+pub const FFT_KERNEL_SIZE: usize = 21;
+
+/// Correlate each channel with a kernel of any size, ie. convolve with the flipped one.
+/// Output is shrunk by the kernel size minus 1, see `pad_for_kernel` to keep the size.
+pub fn linear_filter(src: Array3<f32>, kernel: Array2<f32>, job: &Job) -> Result<Array3<f32>> {
+    let (kh, kw) = kernel.dim();
+    let (h, w, _) = src.dim();
+    assert!(0 < kh && kh <= h && 0 < kw && kw <= w);
+
+    if kh * kw >= FFT_KERNEL_SIZE * FFT_KERNEL_SIZE {
+        linear_filter_fft(src, kernel, job)
+    } else {
+        linear_filter_direct(src, kernel, job)
+    }
+}
+
+/// `linear_filter` by definition, O(k^2) per pixel.
+pub fn linear_filter_direct(
+    src: Array3<f32>,
+    kernel: Array2<f32>,
+    job: &Job,
+) -> Result<Array3<f32>> {
+    let (kh, kw) = kernel.dim();
+    let (h, w, ncol) = src.dim();
+    let (h2, w2) = (h - kh + 1, w - kw + 1);
+    let progress = job.progress(h2);
+    let mut dest = Array::zeros((h2, w2, ncol));
+    Zip::indexed(&mut dest).par_apply(|(x, y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if y == 0 && col == 0 {
+            progress.tick();
+        }
+        *v = (&src.slice(s![x..x + kh, y..y + kw, col]) * &kernel).sum();
+    });
+
+    job.check()?;
+    Ok(dest)
+}
+
+/// `linear_filter` via FFT, O(log(hw)) per pixel.
+pub fn linear_filter_fft(src: Array3<f32>, kernel: Array2<f32>, job: &Job) -> Result<Array3<f32>> {
+    let (kh, kw) = kernel.dim();
+    let (h, w, ncol) = src.dim();
+    let (h2, w2) = (h - kh + 1, w - kw + 1);
+    // Pad to power of two for speed. Values wrapped around only affect pixels out of the output.
+    let (n, m) = (h.next_power_of_two(), w.next_power_of_two());
+    let nparts = 1 + 2 * ncol;
+
+    let mut kernel_pad = Array::zeros((n, m));
+    kernel_pad
+        .slice_mut(s![..kh, ..kw])
+        .assign(&kernel.mapv(C::from));
+    // Correlation is the product with the conjugated spectrum of the kernel.
+    let kernel_fft = fft_2d(kernel_pad, false, &job.part(0, nparts))?.mapv(|v| v.conj());
+    // Compensate the normalization of FFT.
+    let scale = ((n * m) as f32).sqrt();
+
+    let mut dest = Array::zeros((h2, w2, ncol));
+    for col in 0..ncol {
+        let mut chan = Array::zeros((n, m));
+        chan.slice_mut(s![..h, ..w])
+            .assign(&src.slice(s![.., .., col]).mapv(C::from));
+        let mut spectrum = fft_2d(chan, false, &job.part(1 + 2 * col, nparts))?;
+        spectrum *= &kernel_fft;
+        let chan = fft_2d(spectrum, true, &job.part(2 + 2 * col, nparts))?;
+        dest.slice_mut(s![.., .., col])
+            .assign(&chan.slice(s![..h2, ..w2]).mapv(|v| v.re * scale));
+    }
+    Ok(dest)
+}
+
+/// Convolve each channel with the outer product of `kx` along columns and `ky` along rows,
+/// in O(kx + ky) per pixel. Output is shrunk by the kernel size minus 1.
+pub fn separable_filter(
+    src: Array3<f32>,
+    kx: &Array1<f32>,
+    ky: &Array1<f32>,
+    job: &Job,
+) -> Result<Array3<f32>> {
+    let (h, w, ncol) = src.dim();
+    let (kh, kw) = (kx.len(), ky.len());
+    assert!(kh <= h && kw <= w);
+    let (h2, w2) = (h - kh + 1, w - kw + 1);
+    // Two passes.
+    let progress = job.progress(h + h2);
+
+    let mut rows = Array::zeros((h, w2, ncol));
+    Zip::indexed(&mut rows).par_apply(|(x, y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if y == 0 && col == 0 {
+            progress.tick();
+        }
+        *v = src.slice(s![x, y..y + kw, col]).dot(ky);
+    });
+    job.check()?;
+
+    let mut dest = Array::zeros((h2, w2, ncol));
+    Zip::indexed(&mut dest).par_apply(|(x, y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if y == 0 && col == 0 {
+            progress.tick();
+        }
+        *v = rows.slice(s![x..x + kh, y, col]).dot(kx);
+    });
+    job.check()?;
+    Ok(dest)
+}
+
+/// Summed-area table of `f` applied on each pixel, with a leading row and column of zeros.
+pub fn integral_image(src: &Array3<f32>, f: impl Fn(f32) -> f32) -> Array3<f64> {
+    let (h, w, ncol) = src.dim();
+    let mut sum = Array::zeros((h + 1, w + 1, ncol));
+    for ((x, y, col), &v) in src.indexed_iter() {
+        sum[[x + 1, y + 1, col]] =
+            f(v) as f64 + sum[[x, y + 1, col]] + sum[[x + 1, y, col]] - sum[[x, y, col]];
+    }
+    sum
+}
+
+/// Sums of all `size`x`size` windows from an integral image, in O(1) per window.
+pub fn window_sums(sum: &Array3<f64>, size: usize) -> Array3<f64> {
+    let (h, w, ncol) = sum.dim();
+    Array::from_shape_fn((h - size, w - size, ncol), |(x, y, col)| {
+        let (x2, y2) = (x + size, y + size);
+        sum[[x2, y2, col]] - sum[[x, y2, col]] - sum[[x2, y, col]] + sum[[x, y, col]]
+    })
+}
+
+/// Mean of each window by an integral image, in O(1) per pixel.
+/// Output is shrunk by `neighbor - 1`.
+pub fn box_filter(src: Array3<f32>, neighbor: usize, job: &Job) -> Result<Array3<f32>> {
+    let (h, w, _) = src.dim();
+    assert!(neighbor <= h && neighbor <= w);
+    let area = (neighbor * neighbor) as f64;
+    let progress = job.progress(1);
+    let mean = window_sums(&integral_image(&src, |v| v), neighbor).mapv(|v| (v / area) as f32);
+    job.check()?;
+    progress.tick();
+    Ok(mean)
+}
+
+/// Adaptive local noise reduction. Output is shrunk by `neighbor - 1`.
+/// Local means and variances are computed by integral images.
+///
+/// https://bokjan.com/2018/11/lab-digital-image-processing.html#menu_index_19
+pub fn wiener_filter(src: Array3<f32>, neighbor: usize, job: &Job) -> Result<Array3<f32>> {
+    let (h, w, _) = src.dim();
+    assert!(neighbor <= h && neighbor <= w);
+    let mid = neighbor / 2;
+    let area = (neighbor * neighbor) as f64;
+    // Two integral images, then rows of the output.
+    let progress = job.progress(2 + h - neighbor + 1);
+
+    let mean = window_sums(&integral_image(&src, |v| v), neighbor) / area;
+    job.check()?;
+    progress.tick();
+    let sq_mean = window_sums(&integral_image(&src, |v| v * v), neighbor) / area;
+    job.check()?;
+    progress.tick();
+    // Rounding errors may give tiny negative variances.
+    let dev = (sq_mean - &mean * &mean).mapv(|v| v.max(0.0) as f32);
+    let nu2 = dev.mean().unwrap();
+
+    let mut dest = mean.mapv(|v| v as f32);
+    Zip::indexed(&mut dest)
+        .and(&dev)
+        .par_apply(|(x, y, col), v, &dev| {
+            if job.is_cancelled() {
+                return;
+            }
+            if y == 0 && col == 0 {
+                progress.tick();
+            }
+            let mean = *v;
+            // Flat regions of a flat image have no variance at all.
+            let gain = if dev > 0.0 {
+                (dev - nu2).max(0.) / dev.max(nu2)
+            } else {
+                0.0
+            };
+            *v = mean + gain * (src[[x + mid, y + mid, col]] - mean);
+        });
+
+    job.check()?;
+    Ok(dest)
+}
+
+/// Edge-preserving smoothing. Output is shrunk by `neighbor - 1`.
+pub fn bilateral_filter(
+    src: Array3<f32>,
+    neighbor: usize,
+    sigma_d: f32,
+    sigma_r: f32,
+    job: &Job,
+) -> Result<Array3<f32>> {
+    let (h, w, ncol) = src.dim();
+    assert!(neighbor <= h && neighbor <= w);
+    let (h2, w2) = (h - neighbor + 1, w - neighbor + 1);
+    let mid = neighbor / 2;
+    let progress = job.progress(h2);
+
+    let mut dest = Array::zeros((h2, w2, ncol));
+    Zip::indexed(&mut dest).par_apply(|(x, y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if y == 0 && col == 0 {
+            progress.tick();
+        }
+        let (mut sum, mut wsum) = (0.0, 0.0);
+        for i in 0..neighbor {
+            for j in 0..neighbor {
+                let dd = ((i as f32 - mid as f32).powi(2) + (j as f32 - mid as f32).powi(2))
+                    / (2.0 * sigma_d.powi(2));
+                let dr = (src[[x + i, y + j, col]] - src[[x + mid, y + mid, col]])
+                    .abs()
+                    .powi(2)
+                    / (2.0 * sigma_r.powi(2));
+                let w = (-dd - dr).exp();
+                wsum += w;
+                sum += src[[x + i, y + j, col]] * w;
+            }
+        }
+        *v = sum / wsum;
+    });
+
+    job.check()?;
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fast_filters() {
+        let job = Job::default();
+        let src = Array::from_shape_fn((15, 12, 2), |(x, y, col)| {
+            ((x * 5 + y * 3 + col) % 7) as f32 / 7.0
+        });
+        let check = |a: Array3<f32>, b: Array3<f32>| {
+            assert_eq!(a.dim(), b.dim());
+            for (a, b) in a.iter().zip(b.iter()) {
+                assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+            }
+        };
+
+        let direct = linear_filter_direct(src.clone(), box_filter_kernel(5), &job).unwrap();
+        check(box_filter(src.clone(), 5, &job).unwrap(), direct);
+
+        let direct = linear_filter_direct(src.clone(), gauss_filter_kernel(7, 2.0), &job).unwrap();
+        let kernel = gauss_kernel_1d(7, 2.0);
+        check(
+            separable_filter(src, &kernel, &kernel, &job).unwrap(),
+            direct,
+        );
+    }
+
+    #[test]
+    fn test_linear_filter_fft() {
+        let job = Job::default();
+        let src = Array::from_shape_fn((20, 27, 2), |(x, y, col)| {
+            ((x * 7 + y * 13 + col * 5) % 11) as f32 / 11.0
+        });
+        let kernel = Array::from_shape_fn((9, 9), |(x, y)| (x as f32 - y as f32 * 0.5).sin());
+        let direct = linear_filter_direct(src.clone(), kernel.clone(), &job).unwrap();
+        let fft = linear_filter_fft(src, kernel, &job).unwrap();
+        assert_eq!(direct.dim(), fft.dim());
+        for (a, b) in direct.iter().zip(fft.iter()) {
+            assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+        }
+    }
+
+    #[test]
+    fn test_custom_kernel() {
+        let job = Job::default();
+        let src = Array::from_shape_fn((20, 27, 2), |(x, y, col)| {
+            ((x * 7 + y * 13 + col * 5) % 11) as f32 / 11.0
+        });
+        let kernel = Array::from_shape_fn((9, 6), |(x, y)| (x as f32 - y as f32 * 0.5).sin());
+        let direct = linear_filter_direct(src.clone(), kernel.clone(), &job).unwrap();
+        let fft = linear_filter_fft(src, kernel, &job).unwrap();
+        assert_eq!(direct.dim(), (12, 22, 2));
+        assert_eq!(direct.dim(), fft.dim());
+        for (a, b) in direct.iter().zip(fft.iter()) {
+            assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+        }
+
+        // Custom kernels of even and non-square sizes, anchored at the middle.
+        let src = Array::from_shape_fn((6, 5, 1), |(x, y, _)| (x * 5 + y) as f32);
+        let run = |kernel: &str, normalize| {
+            let params = FilterParams {
+                ty: FilterType::Custom,
+                kernel: kernel.to_owned(),
+                normalize,
+                border: Border::Replicate,
+                ..FilterParams::defaults()
+            };
+            params.check().unwrap();
+            let dest = Filter
+                .run(&params, Image::Normal(src.clone()), &job)
+                .unwrap()
+                .expect_normal()
+                .unwrap();
+            assert_eq!(dest.dim(), src.dim());
+            dest
+        };
+        let dest = run("0 0 2", true);
+        assert_eq!(dest.slice(s![.., ..4, ..]), src.slice(s![.., 1.., ..]));
+        let dest = run("-1; 1", false);
+        assert!(dest.slice(s![1.., .., ..]).iter().all(|&v| v == 5.0));
+        assert!(FilterParams {
+            ty: FilterType::Custom,
+            kernel: "1 2; 3".to_owned(),
+            ..FilterParams::defaults()
+        }
+        .check()
+        .is_err());
+    }
+
+    #[test]
+    fn test_border_modes() {
+        let job = Job::default();
+        // Borders keep the size, and constant images for normalized kernels.
+        let src = Array::from_elem((6, 5, 1), 0.5);
+        for &border in &[
+            Border::Reflect,
+            Border::Replicate,
+            Border::Wrap,
+            Border::Constant,
+        ] {
+            for &ty in &[FilterType::Box, FilterType::Wiener, FilterType::Bilateral] {
+                let params = FilterParams {
+                    ty,
+                    neighbor: 7,
+                    border,
+                    border_value: 0.5,
+                    ..FilterParams::defaults()
+                };
+                let dest = Filter
+                    .run(&params, Image::Normal(src.clone()), &job)
+                    .unwrap()
+                    .expect_normal()
+                    .unwrap();
+                assert_eq!(dest.dim(), src.dim());
+                assert!(
+                    dest.iter().all(|v| (v - 0.5).abs() < 1e-5),
+                    "{:?} {:?}",
+                    ty,
+                    border,
+                );
+            }
+        }
+    }
+}
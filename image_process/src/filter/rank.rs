@@ -0,0 +1,310 @@
+use crate::{processor::Job, Result};
+use ndarray::{prelude::*, Zip};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+
+/// The `percentile`-th value in each window, by sorting. Output is shrunk by `neighbor - 1`.
+pub fn rank_filter(
+    src: Array3<f32>,
+    neighbor: usize,
+    percentile: f32,
+    job: &Job,
+) -> Result<Array3<f32>> {
+    let (h, w, ncol) = src.dim();
+    assert!(neighbor <= h && neighbor <= w);
+    let (h2, w2) = (h - neighbor + 1, w - neighbor + 1);
+    let rank = rank_index(neighbor * neighbor, percentile);
+    let progress = job.progress(h2);
+
+    let mut dest = Array::zeros((h2, w2, ncol));
+    Zip::indexed(&mut dest).par_apply(|(x, y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if y == 0 && col == 0 {
+            progress.tick();
+        }
+        let window = src.slice(s![x..x + neighbor, y..y + neighbor, col]);
+        let mut values = window.iter().copied().collect::<Vec<_>>();
+        values.sort_unstable_by(cmp_nan_last);
+        *v = values[rank];
+    });
+
+    job.check()?;
+    Ok(dest)
+}
+
+/// Median of each window. Images of 8-bit samples use sliding histograms (Huang's algorithm)
+/// in O(neighbor) per pixel, others fall back to `rank_filter`.
+/// Output is shrunk by `neighbor - 1`.
+pub fn median_filter(src: Array3<f32>, neighbor: usize, job: &Job) -> Result<Array3<f32>> {
+    let (h, w, ncol) = src.dim();
+    assert!(neighbor <= h && neighbor <= w);
+    let levels = match levels_8bit(&src) {
+        Some(levels) => levels,
+        None => return rank_filter(src, neighbor, 50.0, job),
+    };
+    let (h2, w2) = (h - neighbor + 1, w - neighbor + 1);
+    let rank = rank_index(neighbor * neighbor, 50.0);
+    let progress = job.progress(h2);
+
+    let mut dest = Array::zeros((h2, w2, ncol));
+    dest.axis_iter_mut(Axis(0))
+        .into_par_iter()
+        .enumerate()
+        .for_each(|(x, mut row)| {
+            if job.is_cancelled() {
+                return;
+            }
+            for col in 0..ncol {
+                let strip = levels.slice(s![x..x + neighbor, .., col]);
+                let mut hist = [0usize; 256];
+                for &l in strip.slice(s![.., ..neighbor - 1]) {
+                    hist[l as usize] += 1;
+                }
+                for y in 0..w2 {
+                    // Slide the window right by a column.
+                    for &l in strip.column(y + neighbor - 1) {
+                        hist[l as usize] += 1;
+                    }
+                    let mut count = 0;
+                    let median = (0..256)
+                        .find(|&l| {
+                            count += hist[l];
+                            count > rank
+                        })
+                        .unwrap();
+                    row[[y, col]] = median as f32 / 256.0;
+                    for &l in strip.column(y) {
+                        hist[l as usize] -= 1;
+                    }
+                }
+            }
+            progress.tick();
+        });
+
+    job.check()?;
+    Ok(dest)
+}
+
+/// Minimum, or maximum if `max`, of each window. Square windows are separable, so it costs
+/// O(neighbor) per pixel. Output is shrunk by `neighbor - 1`.
+pub fn extremum_filter(
+    src: Array3<f32>,
+    neighbor: usize,
+    max: bool,
+    job: &Job,
+) -> Result<Array3<f32>> {
+    let (h, w, ncol) = src.dim();
+    assert!(neighbor <= h && neighbor <= w);
+    let (h2, w2) = (h - neighbor + 1, w - neighbor + 1);
+    let pick = |a: f32, b: f32| if max { a.max(b) } else { a.min(b) };
+    let init = if max {
+        f32::NEG_INFINITY
+    } else {
+        f32::INFINITY
+    };
+    // Two passes.
+    let progress = job.progress(h + h2);
+
+    let mut rows = Array::zeros((h, w2, ncol));
+    Zip::indexed(&mut rows).par_apply(|(x, y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if y == 0 && col == 0 {
+            progress.tick();
+        }
+        *v = src
+            .slice(s![x, y..y + neighbor, col])
+            .fold(init, |a, &b| pick(a, b));
+    });
+    job.check()?;
+
+    let mut dest = Array::zeros((h2, w2, ncol));
+    Zip::indexed(&mut dest).par_apply(|(x, y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if y == 0 && col == 0 {
+            progress.tick();
+        }
+        *v = rows
+            .slice(s![x..x + neighbor, y, col])
+            .fold(init, |a, &b| pick(a, b));
+    });
+    job.check()?;
+    Ok(dest)
+}
+
+/// Median filter with windows growing from 3x3 up to `neighbor`, until the median is not an
+/// impulse. Pixels which are not impulses are kept, so details are better preserved.
+/// Output is shrunk by `neighbor - 1`.
+pub fn adaptive_median_filter(src: Array3<f32>, neighbor: usize, job: &Job) -> Result<Array3<f32>> {
+    let (h, w, ncol) = src.dim();
+    assert!(neighbor <= h && neighbor <= w);
+    let (h2, w2) = (h - neighbor + 1, w - neighbor + 1);
+    let mid = neighbor / 2;
+    let progress = job.progress(h2);
+
+    let mut dest = Array::zeros((h2, w2, ncol));
+    Zip::indexed(&mut dest).par_apply(|(x, y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if y == 0 && col == 0 {
+            progress.tick();
+        }
+        let (cx, cy) = (x + mid, y + mid);
+        let center = src[[cx, cy, col]];
+        let mut median = center;
+        for r in 1..=mid {
+            let window = src.slice(s![cx - r..=cx + r, cy - r..=cy + r, col]);
+            let mut values = window.iter().copied().collect::<Vec<_>>();
+            values.sort_unstable_by(cmp_nan_last);
+            let (min, max) = (values[0], values[values.len() - 1]);
+            median = values[values.len() / 2];
+            if min < median && median < max {
+                if min < center && center < max {
+                    median = center;
+                }
+                break;
+            }
+        }
+        *v = median;
+    });
+
+    job.check()?;
+    Ok(dest)
+}
+
+/// Levels `l` of 8-bit samples stored as `l / 256`, as `Image::open` does,
+/// or `None` if any sample is not.
+fn levels_8bit(src: &Array3<f32>) -> Option<Array3<u8>> {
+    let levels = src
+        .iter()
+        .map(|&v| {
+            let l = (v * 256.0).round();
+            if (0.0..=255.0).contains(&l) && l / 256.0 == v {
+                Some(l as u8)
+            } else {
+                None
+            }
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Array::from_shape_vec(src.raw_dim(), levels).unwrap())
+}
+
+/// Total order of samples, with NaN greater than any other value. NaN and infinities can come
+/// from frequency domain steps earlier in a pipeline.
+fn cmp_nan_last(a: &f32, b: &f32) -> Ordering {
+    a.partial_cmp(b)
+        .unwrap_or_else(|| a.is_nan().cmp(&b.is_nan()))
+}
+
+/// Index of the `percentile`-th element among `n` sorted elements.
+fn rank_index(n: usize, percentile: f32) -> usize {
+    (percentile / 100.0 * (n - 1) as f32).round() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::Image;
+    use std::path::Path;
+
+    /// The brightest 8-bit sample.
+    const WHITE: f32 = 255.0 / 256.0;
+
+    #[test]
+    fn test_rank_filters() {
+        let job = Job::default();
+        // Levels of 8 bits, with impulses.
+        let src = Array::from_shape_fn((12, 14, 2), |(x, y, col)| {
+            match (x * 7 + y * 5 + col) % 11 {
+                0 => 0.0,
+                1 => WHITE,
+                v => (100 + v * 3) as f32 / 256.0,
+            }
+        });
+        assert!(levels_8bit(&src).is_some());
+
+        let median = median_filter(src.clone(), 5, &job).unwrap();
+        let sorted = rank_filter(src.clone(), 5, 50.0, &job).unwrap();
+        assert_eq!(median.dim(), (8, 10, 2));
+        for (a, b) in median.iter().zip(&sorted) {
+            assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+        }
+        // Impulses are removed.
+        assert!(median.iter().all(|&v| 0.0 < v && v < WHITE));
+
+        let min = extremum_filter(src.clone(), 3, false, &job).unwrap();
+        assert_eq!(min, rank_filter(src.clone(), 3, 0.0, &job).unwrap());
+        let max = extremum_filter(src.clone(), 3, true, &job).unwrap();
+        assert_eq!(max, rank_filter(src.clone(), 3, 100.0, &job).unwrap());
+
+        // Pixels other than impulses are better preserved.
+        let adaptive = adaptive_median_filter(src.clone(), 5, &job).unwrap();
+        assert!(adaptive.iter().all(|&v| 0.0 < v && v < WHITE));
+        let error = |dest: &Array3<f32>| {
+            let mut sum = 0.0;
+            for ((x, y, col), &v) in dest.indexed_iter() {
+                let orig = src[[x + 2, y + 2, col]];
+                if 0.0 < orig && orig < WHITE {
+                    sum += (v - orig).abs();
+                }
+            }
+            sum
+        };
+        assert!(error(&adaptive) < error(&median) * 0.5);
+    }
+
+    #[test]
+    fn test_median_filter_exact() {
+        let job = Job::default();
+        let sample = |x: usize, y: usize, col: usize| (x * 7 + y * 5 + col * 3) % 11;
+        // Samples of 16 bits, and out of [0, 1] as produced by sharpening kernels.
+        let deep = Array::from_shape_fn((9, 10, 2), |(x, y, col)| {
+            (sample(x, y, col) * 997) as f32 / 65535.0
+        });
+        let wide = Array::from_shape_fn((9, 10, 2), |(x, y, col)| {
+            sample(x, y, col) as f32 * 0.3 - 1.2
+        });
+        for src in &[deep, wide] {
+            assert!(levels_8bit(src).is_none());
+            let median = median_filter(src.clone(), 3, &job).unwrap();
+            let sorted = rank_filter(src.clone(), 3, 50.0, &job).unwrap();
+            assert_eq!(median, sorted);
+        }
+    }
+
+    #[test]
+    fn test_non_finite() {
+        let job = Job::default();
+        let mut src = Array::from_shape_fn((5, 5, 1), |(x, y, _)| (x * 5 + y) as f32);
+        src[[1, 1, 0]] = f32::NAN;
+        src[[2, 2, 0]] = f32::INFINITY;
+        src[[3, 3, 0]] = f32::NEG_INFINITY;
+        let min = rank_filter(src.clone(), 5, 0.0, &job).unwrap();
+        assert_eq!(min[[0, 0, 0]], f32::NEG_INFINITY);
+        let max = rank_filter(src.clone(), 5, 100.0, &job).unwrap();
+        assert!(max[[0, 0, 0]].is_nan());
+        // 6 and 18 are replaced by the infinities at both ends, and 12 by NaN.
+        let median = median_filter(src.clone(), 5, &job).unwrap();
+        assert_eq!(median[[0, 0, 0]], 13.0);
+        adaptive_median_filter(src, 5, &job).unwrap();
+    }
+
+    #[test]
+    fn test_median_filter_loaded() {
+        let job = Job::default();
+        let src = Image::open(Path::new("assets/ddg_small.png"))
+            .unwrap()
+            .expect_normal()
+            .unwrap();
+        // Sliding histograms are used on images loaded from 8-bit files.
+        assert!(levels_8bit(&src).is_some());
+        let median = median_filter(src.clone(), 5, &job).unwrap();
+        assert_eq!(median, rank_filter(src, 5, 50.0, &job).unwrap());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::{
+    image::Image,
+    processor::{Job, ParamKind, ParamSpec, Params, Processor},
+    Result,
+};
+use ndarray::{prelude::*, Zip};
+use serde::{Deserialize, Serialize};
+
+/// Filters on the centered spectrum produced by `dft`.
+pub struct FreqFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FreqFilterType {
+    LowPass,
+    HighPass,
+    BandPass,
+    BandReject,
+    /// Reject a symmetric pair of frequencies at `±(notch_u, notch_v)` from the center.
+    Notch,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FreqFilterShape {
+    Ideal,
+    Butterworth,
+    Gauss,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreqFilterParams {
+    #[serde(rename = "type")]
+    pub ty: FreqFilterType,
+    pub shape: FreqFilterShape,
+    /// Cutoff distance from the center, or the radius of notches.
+    pub cutoff: f32,
+    /// Width of the band of band filters.
+    pub width: f32,
+    /// Order of butterworth filters.
+    pub order: u32,
+    pub notch_u: f32,
+    pub notch_v: f32,
+}
+
+impl Params for FreqFilterParams {
+    const SPECS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "type",
+            desc: "Filter type",
+            kind: ParamKind::Choice {
+                choices: &["low_pass", "high_pass", "band_pass", "band_reject", "notch"],
+                default: "low_pass",
+            },
+        },
+        ParamSpec {
+            name: "shape",
+            desc: "Shape of transfer function",
+            kind: ParamKind::Choice {
+                choices: &["ideal", "butterworth", "gauss"],
+                default: "gauss",
+            },
+        },
+        ParamSpec {
+            name: "cutoff",
+            desc: "Cutoff frequency, or band center, or notch radius, in pixels from the center",
+            kind: ParamKind::Float {
+                min: 0.5,
+                max: 10000.0,
+                default: 30.0,
+            },
+        },
+        ParamSpec {
+            name: "width",
+            desc: "Band width of band filters",
+            kind: ParamKind::Float {
+                min: 0.5,
+                max: 10000.0,
+                default: 10.0,
+            },
+        },
+        ParamSpec {
+            name: "order",
+            desc: "Order of butterworth filters",
+            kind: ParamKind::Int {
+                min: 1,
+                max: 20,
+                default: 2,
+            },
+        },
+        ParamSpec {
+            name: "notch_u",
+            desc: "Vertical offset of the notch from the center",
+            kind: ParamKind::Float {
+                min: -10000.0,
+                max: 10000.0,
+                default: 0.0,
+            },
+        },
+        ParamSpec {
+            name: "notch_v",
+            desc: "Horizontal offset of the notch from the center",
+            kind: ParamKind::Float {
+                min: -10000.0,
+                max: 10000.0,
+                default: 0.0,
+            },
+        },
+    ];
+}
+
+impl Processor for FreqFilter {
+    const NAME: &'static str = "freq_filter";
+    type Params = FreqFilterParams;
+
+    fn run(&self, params: &FreqFilterParams, src: Image, job: &Job) -> Result<Image> {
+        let mut mat = src.expect_complex()?;
+        let (h, w, _) = mat.dim();
+        let trans = transfer_function(params, h, w);
+        job.check()?;
+        Zip::indexed(&mut mat).par_apply(|(x, y, _), v| *v *= trans[[x, y]]);
+        Ok(Image::Complex(mat))
+    }
+}
+
+/// Transfer function H(u, v) of size (h, w), with the zero frequency at the center.
+pub fn transfer_function(params: &FreqFilterParams, h: usize, w: usize) -> Array2<f32> {
+    let &FreqFilterParams {
+        ty,
+        shape,
+        cutoff,
+        width,
+        order,
+        notch_u,
+        notch_v,
+    } = params;
+    let (cx, cy) = ((h / 2) as f32, (w / 2) as f32);
+    let dist = |x: f32, y: f32| (x * x + y * y).sqrt();
+
+    Array::from_shape_fn((h, w), |(x, y)| {
+        let (u, v) = (x as f32 - cx, y as f32 - cy);
+        let d = dist(u, v);
+        match ty {
+            FreqFilterType::LowPass => low_pass(shape, d, cutoff, order),
+            FreqFilterType::HighPass => 1.0 - low_pass(shape, d, cutoff, order),
+            FreqFilterType::BandReject => band_reject(shape, d, cutoff, width, order),
+            FreqFilterType::BandPass => 1.0 - band_reject(shape, d, cutoff, width, order),
+            FreqFilterType::Notch => {
+                let d1 = dist(u - notch_u, v - notch_v);
+                let d2 = dist(u + notch_u, v + notch_v);
+                (1.0 - low_pass(shape, d1, cutoff, order))
+                    * (1.0 - low_pass(shape, d2, cutoff, order))
+            }
+        }
+    })
+}
+
+fn low_pass(shape: FreqFilterShape, d: f32, d0: f32, order: u32) -> f32 {
+    match shape {
+        FreqFilterShape::Ideal => (d <= d0) as u8 as f32,
+        FreqFilterShape::Butterworth => 1.0 / (1.0 + (d / d0).powi(2 * order as i32)),
+        FreqFilterShape::Gauss => (-d * d / (2.0 * d0 * d0)).exp(),
+    }
+}
+
+/// Reject the band of `width` around `d0`.
+fn band_reject(shape: FreqFilterShape, d: f32, d0: f32, width: f32, order: u32) -> f32 {
+    match shape {
+        FreqFilterShape::Ideal => ((d - d0).abs() > width / 2.0) as u8 as f32,
+        // Division by zero at `d == d0` gives 0 as expected.
+        FreqFilterShape::Butterworth => {
+            1.0 / (1.0 + (d * width / (d * d - d0 * d0)).powi(2 * order as i32))
+        }
+        FreqFilterShape::Gauss => 1.0 - (-((d * d - d0 * d0) / (d * width)).powi(2)).exp(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transfer_function() {
+        let mut params = FreqFilterParams::defaults();
+        let at = |params: &FreqFilterParams, x: usize, y: usize| {
+            transfer_function(params, 65, 64)[[x, y]]
+        };
+        // Center is (32, 32).
+        params.shape = FreqFilterShape::Ideal;
+        assert_eq!(at(&params, 32, 32), 1.0);
+        assert_eq!(at(&params, 32, 62), 1.0);
+        assert_eq!(at(&params, 32, 63), 0.0);
+
+        for &shape in &[FreqFilterShape::Butterworth, FreqFilterShape::Gauss] {
+            params.shape = shape;
+            params.ty = FreqFilterType::HighPass;
+            params.cutoff = 10.0;
+            assert_eq!(at(&params, 32, 32), 0.0);
+            assert!(at(&params, 0, 0) > 0.9);
+
+            params.ty = FreqFilterType::BandReject;
+            params.cutoff = 20.0;
+            assert_eq!(at(&params, 12, 32), 0.0);
+            assert!(at(&params, 32, 32) > 0.99);
+
+            params.ty = FreqFilterType::Notch;
+            params.cutoff = 2.0;
+            params.notch_u = 10.0;
+            params.notch_v = -5.0;
+            assert_eq!(at(&params, 42, 27), 0.0);
+            assert_eq!(at(&params, 22, 37), 0.0);
+            assert!(at(&params, 32, 32) > 0.99);
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use crate::Result;
+use ::image::ColorType;
+use failure::bail;
+use ndarray::{prelude::*, Zip};
+use num_complex::Complex32 as C;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::Path,
+};
+
+/// What to show of a complex image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpectrumView {
+    Magnitude,
+    LogMagnitude,
+    /// Hue-mapped phase. The colormap is ignored.
+    Phase,
+    /// Real part in signed log scale.
+    Real,
+    /// Imaginary part in signed log scale.
+    Imaginary,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Colormap {
+    /// Render each color channel separately.
+    Gray,
+    Viridis,
+    Jet,
+}
+
+/// How to render complex images. Values are normalized to fit the whole range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpectrumStyle {
+    pub view: SpectrumView,
+    pub colormap: Colormap,
+}
+
+impl Default for SpectrumStyle {
+    fn default() -> Self {
+        Self {
+            view: SpectrumView::LogMagnitude,
+            colormap: Colormap::Gray,
+        }
+    }
+}
+
+/// The image to be processed and rendered.
+#[derive(Debug, Clone)]
+pub enum Image {
+    /// [h, w, <channels>] in [0, 1), where channels are gray, gray-alpha, RGB or RGBA.
+    Normal(Array3<f32>),
+    /// [h, w, <channels>], one centered spectrum per channel of the normal image.
+    Complex(Array3<C>),
+}
+
+impl Image {
+    pub fn open(path: &Path) -> Result<Self> {
+        use ::image::{DynamicImage as D, GenericImageView as _};
+
+        // Detect format by content rather than the extension, as gdk-pixbuf does.
+        let img = ::image::io::Reader::open(path)?
+            .with_guessed_format()?
+            .decode()?;
+        let (w, h) = img.dimensions();
+        let (h, w) = (h as usize, w as usize);
+
+        const U8: f32 = 256.0;
+        const U16: f32 = 65536.0;
+        let mat = match img {
+            D::ImageLuma8(buf) => from_raw(buf.into_raw(), (h, w, 1), U8),
+            D::ImageLumaA8(buf) => from_raw(buf.into_raw(), (h, w, 2), U8),
+            D::ImageRgb8(buf) => from_raw(buf.into_raw(), (h, w, 3), U8),
+            D::ImageRgba8(buf) => from_raw(buf.into_raw(), (h, w, 4), U8),
+            D::ImageLuma16(buf) => from_raw(buf.into_raw(), (h, w, 1), U16),
+            D::ImageLumaA16(buf) => from_raw(buf.into_raw(), (h, w, 2), U16),
+            D::ImageRgb16(buf) => from_raw(buf.into_raw(), (h, w, 3), U16),
+            D::ImageRgba16(buf) => from_raw(buf.into_raw(), (h, w, 4), U16),
+            // BGR(A)
+            img if img.color().has_alpha() => from_raw(img.to_rgba8().into_raw(), (h, w, 4), U8),
+            img => from_raw(img.to_rgb8().into_raw(), (h, w, 3), U8),
+        };
+        Ok(Image::Normal(mat))
+    }
+
+    pub fn expect_normal(self) -> Result<Array3<f32>> {
+        match self {
+            Self::Normal(img) => Ok(img),
+            Self::Complex(_) => bail!("Expecting a normal image, found complex image"),
+        }
+    }
+
+    pub fn expect_complex(self) -> Result<Array3<C>> {
+        match self {
+            Self::Complex(img) => Ok(img),
+            Self::Normal(_) => bail!("Expecting a complex image, found normal image"),
+        }
+    }
+
+    /// Save the image. Format is guessed from the extension.
+    ///
+    /// `pfm`, `tif` and `tiff` store 32-bit float values losslessly (PFM has no alpha).
+    /// `png` stores 8-bit samples with the original channels.
+    /// `pgm` stores 8-bit luma. Others store 8-bit RGB.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        match (&*ext, self) {
+            ("pfm", Self::Normal(mat)) => save_pfm(mat, path),
+            ("tif", Self::Normal(mat)) | ("tiff", Self::Normal(mat)) => save_tiff_f32(mat, path),
+            ("pfm", _) | ("tif", _) | ("tiff", _) => {
+                bail!("Complex image can only be saved as a rendered 8-bit image")
+            }
+            ("pgm", Self::Normal(mat)) => {
+                let (h, w, _) = mat.dim();
+                let luma = to_gray(mat).mapv(quantize).into_raw_vec();
+                ::image::save_buffer(path, &luma, w as u32, h as u32, ColorType::L8)?;
+                Ok(())
+            }
+            ("png", Self::Normal(mat)) => {
+                let (h, w, ncol) = mat.dim();
+                let color = [
+                    ColorType::L8,
+                    ColorType::La8,
+                    ColorType::Rgb8,
+                    ColorType::Rgba8,
+                ];
+                let raw = mat.mapv(quantize).into_raw_vec();
+                ::image::save_buffer(path, &raw, w as u32, h as u32, color[ncol - 1])?;
+                Ok(())
+            }
+            _ => {
+                let pixels = self.to_pixels();
+                let (h, w, _) = pixels.dim();
+                let raw_pixels = pixels.slice(s![.., .., ..3]).to_owned().into_raw_vec();
+                ::image::save_buffer(path, &raw_pixels, w as u32, h as u32, ColorType::Rgb8)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Convert to 8-bit RGB or RGBA pixels for displaying.
+    pub fn to_pixels(&self) -> Array3<u8> {
+        self.to_pixels_with(&SpectrumStyle::default())
+    }
+
+    /// Convert to 8-bit RGB or RGBA pixels, rendering complex images in `style`.
+    pub fn to_pixels_with(&self, style: &SpectrumStyle) -> Array3<u8> {
+        match self {
+            Self::Normal(mat) => {
+                let (h, w, ncol) = mat.dim();
+                let has_alpha = color_channels(ncol) != ncol;
+                // Map RGB(A) to source channels.
+                let src_col = |col: usize| match col {
+                    3 => ncol - 1,
+                    _ if ncol <= 2 => 0,
+                    _ => col,
+                };
+                Array::from_shape_fn((h, w, if has_alpha { 4 } else { 3 }), |(x, y, col)| {
+                    quantize(mat[[x, y, src_col(col)]])
+                })
+            }
+            Self::Complex(mat) => render_spectrum(mat, style),
+        }
+    }
+}
+
+/// Number of color channels, excluding alpha.
+pub fn color_channels(ncol: usize) -> usize {
+    match ncol {
+        1 | 2 => 1,
+        3 | 4 => 3,
+        _ => panic!("Invalid channel count: {}", ncol),
+    }
+}
+
+/// Luma of color channels. Alpha is ignored.
+pub fn to_gray(mat: &Array3<f32>) -> Array2<f32> {
+    let (h, w, ncol) = mat.dim();
+    if color_channels(ncol) == 1 {
+        return mat.slice(s![.., .., 0]).to_owned();
+    }
+    Array::from_shape_fn((h, w), |(x, y)| {
+        0.299 * mat[[x, y, 0]] + 0.587 * mat[[x, y, 1]] + 0.114 * mat[[x, y, 2]]
+    })
+}
+
+fn render_spectrum(mat: &Array3<C>, style: &SpectrumStyle) -> Array3<u8> {
+    let (h, w, ncol) = mat.dim();
+    let ncolor = color_channels(ncol);
+    let mat = mat.slice(s![.., .., ..ncolor]);
+    let mapped = style.colormap != Colormap::Gray || style.view == SpectrumView::Phase;
+    // The average of spectra is the spectrum of the average of channels.
+    let mat = if mapped && ncolor != 1 {
+        (mat.sum_axis(Axis(2)) / C::from(ncolor as f32)).insert_axis(Axis(2))
+    } else {
+        mat.to_owned()
+    };
+
+    let signed_log = |v: f32| v.signum() * v.abs().ln_1p();
+    let mut values = match style.view {
+        SpectrumView::Magnitude => mat.mapv(|v| v.norm()),
+        SpectrumView::LogMagnitude => mat.mapv(|v| v.norm().ln_1p()),
+        SpectrumView::Phase => mat.mapv(|v| v.arg() / (2.0 * std::f32::consts::PI) + 0.5),
+        SpectrumView::Real => mat.mapv(|v| signed_log(v.re)),
+        SpectrumView::Imaginary => mat.mapv(|v| signed_log(v.im)),
+    };
+    // Normalize to [0, 1]. Zero is kept at the middle for signed values.
+    let max = values.fold(0.0f32, |m, v| m.max(v.abs()));
+    if max > 0.0 {
+        match style.view {
+            SpectrumView::Magnitude | SpectrumView::LogMagnitude => values /= max,
+            SpectrumView::Real | SpectrumView::Imaginary => {
+                values.mapv_inplace(|v| v / max * 0.5 + 0.5)
+            }
+            SpectrumView::Phase => {}
+        }
+    }
+
+    let mut dest = Array::zeros((h, w, 3));
+    Zip::from(dest.genrows_mut())
+        .and(values.genrows())
+        .apply(|mut pixel, v| {
+            let rgb = if mapped {
+                apply_colormap(style, v[0])
+            } else if v.len() == 1 {
+                [v[0]; 3]
+            } else {
+                [v[0], v[1], v[2]]
+            };
+            for (p, &c) in pixel.iter_mut().zip(&rgb) {
+                *p = quantize(c);
+            }
+        });
+    dest
+}
+
+/// Map `v` in [0, 1] to RGB. Components out of [0, 1] are clamped by `quantize`.
+fn apply_colormap(style: &SpectrumStyle, v: f32) -> [f32; 3] {
+    // Samples of viridis at 1/8 intervals.
+    const VIRIDIS: [[u8; 3]; 9] = [
+        [68, 1, 84],
+        [71, 44, 122],
+        [59, 81, 139],
+        [44, 113, 142],
+        [33, 144, 141],
+        [39, 173, 129],
+        [92, 200, 99],
+        [170, 220, 50],
+        [253, 231, 37],
+    ];
+
+    if style.view == SpectrumView::Phase {
+        // Hue of HSV with full saturation and value.
+        let f = |n: f32| {
+            let k = (n + v * 6.0) % 6.0;
+            1.0 - k.min(4.0 - k).max(0.0)
+        };
+        return [f(5.0), f(3.0), f(1.0)];
+    }
+    match style.colormap {
+        Colormap::Gray => [v; 3],
+        Colormap::Viridis => {
+            let t = v * 8.0;
+            let i = (t as usize).min(7);
+            let frac = t - i as f32;
+            let mut rgb = [0.0; 3];
+            for (col, c) in rgb.iter_mut().enumerate() {
+                let (a, b) = (VIRIDIS[i][col] as f32, VIRIDIS[i + 1][col] as f32);
+                *c = (a + (b - a) * frac) / 255.0;
+            }
+            rgb
+        }
+        Colormap::Jet => {
+            let f = |c: f32| 1.5 - (4.0 * v - c).abs();
+            [f(3.0), f(2.0), f(1.0)]
+        }
+    }
+}
+
+fn from_raw<T: Copy + Into<f32>>(raw: Vec<T>, dim: (usize, usize, usize), max: f32) -> Array3<f32> {
+    Array::from_shape_vec(dim, raw)
+        .expect("Should have no row align")
+        .mapv(|v| v.into() / max)
+}
+
+fn quantize(v: f32) -> u8 {
+    (v * 256.0).max(0.0).min(255.0) as u8
+}
+
+/// Portable float map, with little-endian rows from bottom to top.
+fn save_pfm(mat: &Array3<f32>, path: &Path) -> Result<()> {
+    let (h, w, ncol) = mat.dim();
+    let ncolor = color_channels(ncol);
+    let mut f = BufWriter::new(File::create(path)?);
+    let magic = if ncolor == 1 { "Pf" } else { "PF" };
+    write!(f, "{}\n{} {}\n-1.0\n", magic, w, h)?;
+    for row in mat.outer_iter().rev() {
+        for &v in row.slice(s![.., ..ncolor]).iter() {
+            f.write_all(&v.to_le_bytes())?;
+        }
+    }
+    f.flush()?;
+    Ok(())
+}
+
+fn save_tiff_f32(mat: &Array3<f32>, path: &Path) -> Result<()> {
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    let (h, w, ncol) = mat.dim();
+    let (w, h) = (w as u32, h as u32);
+    let mut f = BufWriter::new(File::create(path)?);
+    let mut encoder = TiffEncoder::new(&mut f)?;
+    // Copied only if not in row-major order, eg. transposed or sliced.
+    let mat = mat.as_standard_layout();
+    let buf = mat.as_slice().unwrap();
+    match ncol {
+        1 => encoder.write_image::<colortype::Gray32Float>(w, h, buf)?,
+        3 => encoder.write_image::<colortype::RGB32Float>(w, h, buf)?,
+        4 => encoder.write_image::<colortype::RGBA32Float>(w, h, buf)?,
+        // Gray-alpha is not supported. Expand to RGBA.
+        _ => {
+            let buf = Array::from_shape_fn((h as usize, w as usize, 4), |(x, y, col)| {
+                mat[[x, y, if col == 3 { 1 } else { 0 }]]
+            });
+            encoder.write_image::<colortype::RGBA32Float>(w, h, buf.as_slice().unwrap())?
+        }
+    }
+    f.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_lossless() {
+        let mat = Array::from_shape_fn((3, 4, 3), |(x, y, col)| {
+            (x * 12 + y * 3 + col) as f32 / 7.0 - 1.0
+        });
+        let img = Image::Normal(mat.clone());
+        let dir = std::env::temp_dir();
+
+        let path = dir.join("vision_works_test_save.tiff");
+        img.save(&path).unwrap();
+        let mut decoder = tiff::decoder::Decoder::new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(decoder.dimensions().unwrap(), (4, 3));
+        match decoder.read_image().unwrap() {
+            tiff::decoder::DecodingResult::F32(buf) => assert_eq!(buf, mat.clone().into_raw_vec()),
+            _ => panic!("Should be f32 image"),
+        }
+
+        let path = dir.join("vision_works_test_save.pfm");
+        img.save(&path).unwrap();
+        let buf = std::fs::read(&path).unwrap();
+        let header = b"PF\n4 3\n-1.0\n";
+        assert_eq!(&buf[..header.len()], header);
+        // Bottom row first.
+        let first = f32::from_le_bytes([buf[12], buf[13], buf[14], buf[15]]);
+        assert_eq!(first, mat[[2, 0, 0]]);
+        assert_eq!(buf.len(), header.len() + 3 * 4 * 3 * 4);
+
+        // Not in row-major order.
+        let mut flipped = mat.clone();
+        flipped.invert_axis(Axis(1));
+        let path = dir.join("vision_works_test_save_flipped.tiff");
+        Image::Normal(flipped.clone()).save(&path).unwrap();
+        let mut decoder = tiff::decoder::Decoder::new(File::open(&path).unwrap()).unwrap();
+        match decoder.read_image().unwrap() {
+            tiff::decoder::DecodingResult::F32(buf) => {
+                assert_eq!(buf, flipped.iter().copied().collect::<Vec<_>>())
+            }
+            _ => panic!("Should be f32 image"),
+        }
+    }
+
+    #[test]
+    fn test_open_gray_alpha_16() {
+        let raw: Vec<u16> = (0..3 * 4 * 2).map(|i| i * 1000 + 1).collect();
+        let buf = ::image::ImageBuffer::<::image::LumaA<u16>, _>::from_raw(4, 3, raw).unwrap();
+        let path = std::env::temp_dir().join("vision_works_test_open.png");
+        buf.save(&path).unwrap();
+
+        let mat = Image::open(&path).unwrap().expect_normal().unwrap();
+        assert_eq!(mat.dim(), (3, 4, 2));
+        assert_eq!(mat[[2, 3, 1]], 23001.0 / 65536.0);
+        assert_eq!(Image::Normal(mat).to_pixels().dim(), (3, 4, 4));
+    }
+
+    #[test]
+    fn test_spectrum_style() {
+        let mat = Array::from_shape_vec(
+            (1, 3, 1),
+            vec![C::new(0.0, 0.0), C::new(-5.0, 0.0), C::new(0.0, 2.0)],
+        )
+        .unwrap();
+        let img = Image::Complex(mat);
+        let render = |view, colormap| img.to_pixels_with(&SpectrumStyle { view, colormap });
+
+        let pixels = render(SpectrumView::Magnitude, Colormap::Gray);
+        assert_eq!(pixels.slice(s![0, .., 0]).to_vec(), vec![0, 255, 102]);
+        let pixels = render(SpectrumView::Real, Colormap::Gray);
+        assert_eq!(pixels.slice(s![0, .., 0]).to_vec(), vec![128, 0, 128]);
+        let pixels = render(SpectrumView::Phase, Colormap::Gray);
+        // Phase of `-5` is `pi`, which is red as well as `-pi`.
+        assert_eq!(pixels.slice(s![0, 1, ..]).to_vec(), vec![255, 0, 0]);
+        let pixels = render(SpectrumView::LogMagnitude, Colormap::Viridis);
+        assert_eq!(pixels.slice(s![0, 0, ..]).to_vec(), vec![68, 1, 84]);
+        assert_eq!(pixels.slice(s![0, 1, ..]).to_vec(), vec![253, 231, 37]);
+    }
+}
@@ -0,0 +1,18 @@
+//! Image processing algorithms behind `img_process`, usable without any GUI.
+
+pub mod affine;
+pub mod dct;
+pub mod deconv;
+pub mod dft;
+pub mod edge;
+pub mod filter;
+pub mod freq_filter;
+pub mod image;
+pub mod morphology;
+pub mod noise;
+pub mod pipeline;
+pub mod processor;
+pub mod register;
+mod util;
+
+pub use util::{OptionExt, Result};
@@ -0,0 +1,311 @@
+use crate::{
+    filter::{extremum_filter, pad_border, parse_matrix, Border},
+    image::{color_channels, Image},
+    processor::{Job, ParamKind, ParamSpec, Params, Processor},
+    Result,
+};
+use failure::ensure;
+use ndarray::{prelude::*, Zip};
+use serde::{Deserialize, Serialize};
+
+/// Morphological operations with a flat structuring element, on grayscale or binary images.
+pub struct Morphology;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MorphOp {
+    Erode,
+    Dilate,
+    /// Erosion followed by dilation.
+    Open,
+    /// Dilation followed by erosion.
+    Close,
+    /// Dilation minus erosion.
+    Gradient,
+    /// The image minus its opening.
+    TopHat,
+    /// Closing of the image minus itself.
+    BlackHat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MorphShape {
+    Square,
+    Cross,
+    Disk,
+    /// Parsed from `custom`.
+    Custom,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MorphParams {
+    pub op: MorphOp,
+    pub shape: MorphShape,
+    pub size: usize,
+    /// Matrix of the custom structuring element, where non-zero entries are included.
+    /// See `parse_matrix` for the format.
+    pub custom: String,
+    /// Threshold at 0.5 before the operation.
+    pub binary: bool,
+    pub border: Border,
+    /// Value of pixels out of the image for `Border::Constant`.
+    pub border_value: f32,
+}
+
+impl Params for MorphParams {
+    const SPECS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "op",
+            desc: "Morphological operation",
+            kind: ParamKind::Choice {
+                choices: &[
+                    "erode",
+                    "dilate",
+                    "open",
+                    "close",
+                    "gradient",
+                    "top_hat",
+                    "black_hat",
+                ],
+                default: "erode",
+            },
+        },
+        ParamSpec {
+            name: "shape",
+            desc: "Shape of the structuring element",
+            kind: ParamKind::Choice {
+                choices: &["square", "cross", "disk", "custom"],
+                default: "square",
+            },
+        },
+        ParamSpec {
+            name: "size",
+            desc: "Size of the structuring element, should be odd",
+            kind: ParamKind::Int {
+                min: 1,
+                max: 255,
+                default: 3,
+            },
+        },
+        ParamSpec {
+            name: "custom",
+            desc: "Custom structuring element, eg. `0 1 0; 1 1 1; 0 1 0`, centered at the middle",
+            kind: ParamKind::Text {
+                default: "0 1 0; 1 1 1; 0 1 0",
+            },
+        },
+        ParamSpec {
+            name: "binary",
+            desc: "Threshold the image at 0.5 first",
+            kind: ParamKind::Bool { default: false },
+        },
+        ParamSpec {
+            name: "border",
+            desc: "Extrapolation of pixels out of the image, to keep the size",
+            kind: ParamKind::Choice {
+                choices: &["reflect", "replicate", "wrap", "constant"],
+                default: "replicate",
+            },
+        },
+        ParamSpec {
+            name: "border_value",
+            desc: "Value of pixels out of the image for constant border",
+            kind: ParamKind::Float {
+                min: 0.0,
+                max: 1.0,
+                default: 0.0,
+            },
+        },
+    ];
+
+    fn check(&self) -> Result<()> {
+        ensure!(self.size % 2 == 1, "Size should be odd number");
+        self.structuring_element()?;
+        Ok(())
+    }
+}
+
+impl MorphParams {
+    pub fn structuring_element(&self) -> Result<Array2<bool>> {
+        let se = match self.shape {
+            MorphShape::Custom => parse_matrix(&self.custom)?.mapv(|v| v != 0.0),
+            shape => structuring_element(shape, self.size),
+        };
+        ensure!(
+            se.iter().any(|&v| v),
+            "Structuring element should not be empty",
+        );
+        Ok(se)
+    }
+}
+
+impl Processor for Morphology {
+    const NAME: &'static str = "morphology";
+    type Params = MorphParams;
+
+    fn run(&self, params: &MorphParams, src: Image, job: &Job) -> Result<Image> {
+        let mut mat = src.expect_normal()?;
+        let se = params.structuring_element()?;
+        // Alpha is kept.
+        let color = s![.., .., ..color_channels(mat.dim().2)];
+        let mut src = mat.slice(color).to_owned();
+        if params.binary {
+            src.mapv_inplace(|v| (v >= 0.5) as u8 as f32);
+        }
+        let dest = morphology(src, params.op, &se, params.border, params.border_value, job)?;
+        mat.slice_mut(color).assign(&dest);
+        Ok(Image::Normal(mat))
+    }
+}
+
+/// Structuring element of `size`x`size` in the shape, except `MorphShape::Custom`.
+pub fn structuring_element(shape: MorphShape, size: usize) -> Array2<bool> {
+    let mid = (size / 2) as isize;
+    Array::from_shape_fn((size, size), |(x, y)| {
+        let (x, y) = (x as isize - mid, y as isize - mid);
+        match shape {
+            MorphShape::Square => true,
+            MorphShape::Cross => x == 0 || y == 0,
+            MorphShape::Disk => x * x + y * y <= mid * mid,
+            MorphShape::Custom => panic!("Custom structuring element has no fixed shape"),
+        }
+    })
+}
+
+/// Apply `op` to each channel. The output has the same size, with borders extrapolated.
+pub fn morphology(
+    src: Array3<f32>,
+    op: MorphOp,
+    se: &Array2<bool>,
+    border: Border,
+    value: f32,
+    job: &Job,
+) -> Result<Array3<f32>> {
+    let erode = |src, job: &Job| extremum(src, se, false, border, value, job);
+    let dilate = |src, job: &Job| extremum(src, se, true, border, value, job);
+    let (job1, job2) = (job.part(0, 2), job.part(1, 2));
+    Ok(match op {
+        MorphOp::Erode => erode(src, job)?,
+        MorphOp::Dilate => dilate(src, job)?,
+        MorphOp::Open => dilate(erode(src, &job1)?, &job2)?,
+        MorphOp::Close => erode(dilate(src, &job1)?, &job2)?,
+        MorphOp::Gradient => dilate(src.clone(), &job1)? - erode(src, &job2)?,
+        MorphOp::TopHat => &src - &dilate(erode(src.clone(), &job1)?, &job2)?,
+        MorphOp::BlackHat => erode(dilate(src.clone(), &job1)?, &job2)? - &src,
+    })
+}
+
+/// Minimum, or maximum if `max`, over the structuring element centered at each pixel.
+/// The element is reflected for the maximum, as dilation is defined.
+fn extremum(
+    src: Array3<f32>,
+    se: &Array2<bool>,
+    max: bool,
+    border: Border,
+    value: f32,
+    job: &Job,
+) -> Result<Array3<f32>> {
+    let (h, w, ncol) = src.dim();
+    let (kh, kw) = se.dim();
+    let radius = kh.max(kw) / 2;
+    let src = pad_border(&src, radius, border, value);
+    // Separable for squares.
+    if kh == kw && kh % 2 == 1 && se.iter().all(|&v| v) {
+        return extremum_filter(src, kh, max, job);
+    }
+
+    let offsets: Vec<(usize, usize)> = se
+        .indexed_iter()
+        .filter(|(_, &v)| v)
+        .map(|((x, y), _)| {
+            let (dx, dy) = (
+                x as isize - (kh / 2) as isize,
+                y as isize - (kw / 2) as isize,
+            );
+            let (dx, dy) = if max { (-dx, -dy) } else { (dx, dy) };
+            (
+                (radius as isize + dx) as usize,
+                (radius as isize + dy) as usize,
+            )
+        })
+        .collect();
+    let progress = job.progress(h);
+
+    let mut dest = Array::zeros((h, w, ncol));
+    Zip::indexed(&mut dest).par_apply(|(x, y, col), v| {
+        if job.is_cancelled() {
+            return;
+        }
+        if y == 0 && col == 0 {
+            progress.tick();
+        }
+        let values = offsets.iter().map(|&(dx, dy)| src[[x + dx, y + dy, col]]);
+        *v = if max {
+            values.fold(f32::NEG_INFINITY, f32::max)
+        } else {
+            values.fold(f32::INFINITY, f32::min)
+        };
+    });
+
+    job.check()?;
+    Ok(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_morphology() {
+        let job = Job::default();
+        // A 5x5 square with a hole, and a lone pixel.
+        let src = Array::from_shape_fn((12, 11, 1), |(x, y, _)| {
+            let square = (2..7).contains(&x) && (2..7).contains(&y) && (x, y) != (4, 4);
+            (square || (x, y) == (9, 9)) as u8 as f32
+        });
+        let run = |op, shape, custom: &str| {
+            let params = MorphParams {
+                op,
+                shape,
+                custom: custom.to_owned(),
+                ..MorphParams::defaults()
+            };
+            params.check().unwrap();
+            let dest = Morphology
+                .run(&params, Image::Normal(src.clone()), &job)
+                .unwrap()
+                .expect_normal()
+                .unwrap();
+            assert_eq!(dest.dim(), src.dim());
+            dest
+        };
+        let count = |mat: &Array3<f32>| mat.sum() as usize;
+
+        assert_eq!(count(&run(MorphOp::Erode, MorphShape::Square, "")), 0);
+        assert_eq!(count(&run(MorphOp::Dilate, MorphShape::Square, "")), 49 + 9);
+        assert_eq!(
+            count(&run(MorphOp::Dilate, MorphShape::Cross, "")),
+            49 - 4 + 5
+        );
+        // Disk of size 3 is a cross.
+        let cross = run(MorphOp::Close, MorphShape::Cross, "");
+        assert_eq!(run(MorphOp::Close, MorphShape::Disk, ""), cross);
+        assert_eq!(count(&cross), 25 + 1);
+        assert_eq!(run(MorphOp::BlackHat, MorphShape::Disk, "")[[4, 4, 0]], 1.0);
+        assert_eq!(count(&run(MorphOp::Open, MorphShape::Square, "")), 0);
+        assert_eq!(count(&run(MorphOp::TopHat, MorphShape::Square, "")), 25);
+        assert_eq!(
+            count(&run(MorphOp::Gradient, MorphShape::Square, "")),
+            49 + 9
+        );
+
+        // An off-center element shifts the image, in opposite directions.
+        let dest = run(MorphOp::Dilate, MorphShape::Custom, "1 0 0");
+        assert_eq!(dest[[9, 8, 0]], 1.0);
+        assert_eq!(dest[[9, 9, 0]], 0.0);
+        let dest = run(MorphOp::Erode, MorphShape::Custom, "1 0 0");
+        assert_eq!(dest[[9, 10, 0]], 1.0);
+        assert_eq!(dest[[9, 9, 0]], 0.0);
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{
+    image::{color_channels, Image},
+    processor::{Job, ParamKind, ParamSpec, Params, Processor},
+    Result,
+};
+use ndarray::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct Noise;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseType {
+    Gauss,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoiseParams {
+    #[serde(rename = "type")]
+    pub ty: NoiseType,
+    pub mu: f32,
+    pub sigma: f32,
+}
+
+impl Params for NoiseParams {
+    const SPECS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "type",
+            desc: "Noise type",
+            kind: ParamKind::Choice {
+                choices: &["gauss"],
+                default: "gauss",
+            },
+        },
+        ParamSpec {
+            name: "mu",
+            desc: "Mean of gaussian noise",
+            kind: ParamKind::Float {
+                min: -1.0,
+                max: 1.0,
+                default: 0.0,
+            },
+        },
+        ParamSpec {
+            name: "sigma",
+            desc: "Standard deviation of gaussian noise",
+            kind: ParamKind::Float {
+                min: 0.0,
+                max: 1.0,
+                default: 0.1,
+            },
+        },
+    ];
+}
+
+impl Processor for Noise {
+    const NAME: &'static str = "noise";
+    type Params = NoiseParams;
+
+    fn run(&self, params: &NoiseParams, src: Image, _job: &Job) -> Result<Image> {
+        let mat = src.expect_normal()?;
+        let dest = match params.ty {
+            NoiseType::Gauss => gauss_noise(mat, params.mu, params.sigma),
+        };
+        Ok(Image::Normal(dest))
+    }
+}
+
+/// Add gaussian noise N(mu, sigma^2) to each pixel. Alpha is kept untouched.
+pub fn gauss_noise(mut src: Array3<f32>, mu: f32, sigma: f32) -> Array3<f32> {
+    use rand::prelude::*;
+    use rayon::prelude::*;
+
+    let ncolor = color_channels(src.dim().2);
+    let gauss = rand_distr::Normal::new(mu, sigma.max(0.0)).unwrap();
+    ndarray::Zip::from(src.slice_mut(s![.., .., ..ncolor]))
+        .into_par_iter()
+        .for_each_init(
+            || rand::thread_rng(),
+            |mut rng, (v,)| *v += gauss.sample(&mut rng),
+        );
+    src
+}
@@ -0,0 +1,230 @@
+use crate::{
+    image::Image,
+    processor::{find_processor, is_cancelled, Args, Job},
+    Result,
+};
+use failure::{ensure, format_err};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Write as _},
+    path::Path,
+};
+
+/// A processor with its arguments.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    pub processor: String,
+    pub args: Args,
+}
+
+impl Step {
+    /// Validate arguments against the processor and fill in defaults.
+    pub fn new(processor: &str, args: &Args) -> Result<Self> {
+        let args = find_processor(processor)?
+            .check_args(args)
+            .map_err(|err| format_err!("Invalid arguments of `{}`: {}", processor, err))?;
+        Ok(Self {
+            processor: processor.to_owned(),
+            args,
+        })
+    }
+
+    pub fn run(&self, src: Image, job: &Job) -> Result<Image> {
+        find_processor(&self.processor)?.run(&self.args, src, job)
+    }
+}
+
+/// A chain of steps with undo/redo history.
+///
+/// Every modification records a new revision, so re-parameterizing or
+/// removing a step can be undone as well as appending one.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    revisions: Vec<Vec<Step>>,
+    current: usize,
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self {
+            revisions: vec![Vec::new()],
+            current: 0,
+        }
+    }
+}
+
+impl Pipeline {
+    pub fn steps(&self) -> &[Step] {
+        &self.revisions[self.current]
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.current > 0
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.current + 1 < self.revisions.len()
+    }
+
+    pub fn undo(&mut self) -> bool {
+        if !self.can_undo() {
+            return false;
+        }
+        self.current -= 1;
+        true
+    }
+
+    pub fn redo(&mut self) -> bool {
+        if !self.can_redo() {
+            return false;
+        }
+        self.current += 1;
+        true
+    }
+
+    /// Record a new revision and drop redo history.
+    fn modify(&mut self, f: impl FnOnce(&mut Vec<Step>)) {
+        let mut steps = self.steps().to_vec();
+        f(&mut steps);
+        self.revisions.truncate(self.current + 1);
+        self.revisions.push(steps);
+        self.current += 1;
+    }
+
+    pub fn push(&mut self, step: Step) {
+        self.modify(|steps| steps.push(step));
+    }
+
+    /// Replace arguments of the `idx`-th step.
+    pub fn set_args(&mut self, idx: usize, args: &Args) -> Result<()> {
+        ensure!(idx < self.steps().len(), "Step {} does not exist", idx);
+        let step = Step::new(&self.steps()[idx].processor, args)?;
+        self.modify(|steps| steps[idx] = step);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, idx: usize) -> Result<()> {
+        ensure!(idx < self.steps().len(), "Step {} does not exist", idx);
+        self.modify(|steps| {
+            steps.remove(idx);
+        });
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        if !self.steps().is_empty() {
+            self.modify(|steps| steps.clear());
+        }
+    }
+
+    /// Run all steps from the original input.
+    pub fn run(&self, img: Image, job: &Job) -> Result<Image> {
+        run_steps(self.steps(), img, job)
+    }
+
+    pub fn to_recipe(&self) -> Recipe {
+        Recipe {
+            steps: self.steps().to_vec(),
+        }
+    }
+}
+
+/// Steps saved as a JSON file, to be replayed on other images.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Recipe {
+    pub steps: Vec<Step>,
+}
+
+impl Recipe {
+    /// Load and validate all steps.
+    pub fn load(path: &Path) -> Result<Self> {
+        let recipe: Self = serde_json::from_reader(File::open(path)?)?;
+        let steps = recipe
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| {
+                Step::new(&step.processor, &step.args)
+                    .map_err(|err| format_err!("Step {}: {}", i, err))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { steps })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, self)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn run(&self, img: Image, job: &Job) -> Result<Image> {
+        run_steps(&self.steps, img, job)
+    }
+}
+
+fn run_steps(steps: &[Step], mut img: Image, job: &Job) -> Result<Image> {
+    for (i, step) in steps.iter().enumerate() {
+        img = step.run(img, &job.part(i, steps.len())).map_err(|err| {
+            if is_cancelled(&err) {
+                err
+            } else {
+                format_err!("Step {} ({}): {}", i, step.processor, err)
+            }
+        })?;
+    }
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_pipeline_history() {
+        let mut pipeline = Pipeline::default();
+        assert!(!pipeline.can_undo() && !pipeline.can_redo());
+
+        pipeline.push(Step::new("noise", &json!({})).unwrap());
+        pipeline.push(Step::new("filter", &json!({ "type": "gauss" })).unwrap());
+        pipeline.set_args(1, &json!({ "type": "wiener" })).unwrap();
+        assert_eq!(pipeline.steps()[1].args["type"], json!("wiener"));
+        assert!(pipeline.set_args(1, &json!({ "neighbor": 2 })).is_err());
+
+        pipeline.undo();
+        assert_eq!(pipeline.steps()[1].args["type"], json!("gauss"));
+        pipeline.undo();
+        assert_eq!(pipeline.steps().len(), 1);
+        pipeline.redo();
+        assert_eq!(pipeline.steps().len(), 2);
+
+        // Modification drops redo history.
+        pipeline.remove(0).unwrap();
+        assert!(!pipeline.can_redo());
+        assert_eq!(pipeline.steps()[0].processor, "filter");
+    }
+
+    #[test]
+    fn test_recipe_file() {
+        let mut pipeline = Pipeline::default();
+        pipeline.push(Step::new("filter", &json!({ "type": "wiener" })).unwrap());
+        pipeline.push(Step::new("dft", &json!({})).unwrap());
+        let recipe = pipeline.to_recipe();
+        let path = std::env::temp_dir().join("vision_works_test_recipe.json");
+        recipe.save(&path).unwrap();
+        assert_eq!(Recipe::load(&path).unwrap(), recipe);
+
+        // Missing arguments are filled with defaults, and invalid ones are rejected.
+        let recipe = json!({ "steps": [{ "processor": "noise", "args": {} }] });
+        std::fs::write(&path, recipe.to_string()).unwrap();
+        assert_eq!(
+            Recipe::load(&path).unwrap().steps[0].args["sigma"],
+            json!(0.1f32)
+        );
+        let recipe = json!({ "steps": [{ "processor": "noise", "args": { "sigma": 2 } }] });
+        std::fs::write(&path, recipe.to_string()).unwrap();
+        assert!(Recipe::load(&path).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+use crate::{image::Image, OptionExt as _, Result};
+use failure::{bail, ensure, format_err, Fail};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Type-erased parameters of a processor, as a JSON object.
+pub type Args = Value;
+
+/// Schema of a single parameter.
+#[derive(Debug, Clone, Copy)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub desc: &'static str,
+    pub kind: ParamKind,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ParamKind {
+    Int {
+        min: i64,
+        max: i64,
+        default: i64,
+    },
+    Float {
+        min: f64,
+        max: f64,
+        default: f64,
+    },
+    Bool {
+        default: bool,
+    },
+    Choice {
+        choices: &'static [&'static str],
+        default: &'static str,
+    },
+    /// Free-form string, eg. a matrix.
+    Text {
+        default: &'static str,
+    },
+}
+
+impl ParamSpec {
+    pub fn default_value(&self) -> Value {
+        match self.kind {
+            ParamKind::Int { default, .. } => default.into(),
+            ParamKind::Float { default, .. } => default.into(),
+            ParamKind::Bool { default } => default.into(),
+            ParamKind::Choice { default, .. } => default.into(),
+            ParamKind::Text { default } => default.into(),
+        }
+    }
+
+    /// Check the type and range of a value.
+    pub fn check(&self, v: &Value) -> Result<()> {
+        match self.kind {
+            ParamKind::Int { min, max, .. } => {
+                let v = v.as_i64().context("Expecting an integer")?;
+                ensure!(min <= v && v <= max, "Should be in [{}, {}]", min, max);
+            }
+            ParamKind::Float { min, max, .. } => {
+                let v = v.as_f64().context("Expecting a number")?;
+                ensure!(min <= v && v <= max, "Should be in [{}, {}]", min, max);
+            }
+            ParamKind::Bool { .. } => {
+                v.as_bool().context("Expecting a boolean")?;
+            }
+            ParamKind::Choice { choices, .. } => {
+                let v = v.as_str().context("Expecting a string")?;
+                ensure!(
+                    choices.contains(&v),
+                    "Should be one of: {}",
+                    choices.join(", "),
+                );
+            }
+            ParamKind::Text { .. } => {
+                v.as_str().context("Expecting a string")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Parse a value from string, eg. command line arguments.
+    pub fn parse(&self, s: &str) -> Result<Value> {
+        let v = match self.kind {
+            ParamKind::Int { .. } => s.parse::<i64>()?.into(),
+            ParamKind::Float { .. } => s.parse::<f64>()?.into(),
+            ParamKind::Bool { .. } => s.parse::<bool>()?.into(),
+            ParamKind::Choice { .. } | ParamKind::Text { .. } => s.into(),
+        };
+        self.check(&v)?;
+        Ok(v)
+    }
+}
+
+impl fmt::Display for ParamSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}. ", self.name, self.desc)?;
+        match self.kind {
+            ParamKind::Int { min, max, default } => {
+                write!(f, "Integer in [{}, {}], default {}", min, max, default)
+            }
+            ParamKind::Float { min, max, default } => {
+                write!(f, "Number in [{}, {}], default {}", min, max, default)
+            }
+            ParamKind::Bool { default } => write!(f, "Boolean, default {}", default),
+            ParamKind::Choice { choices, default } => {
+                write!(f, "One of {}, default {}", choices.join("/"), default)
+            }
+            ParamKind::Text { default } => write!(f, "String, default {:?}", default),
+        }
+    }
+}
+
+/// Typed parameters of a processor, described by `SPECS`.
+pub trait Params:
+    fmt::Debug + Clone + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+    const SPECS: &'static [ParamSpec];
+
+    /// Extra checks beyond the ranges in `SPECS`.
+    fn check(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Validate and parse from type-erased arguments. Missing fields are set to defaults.
+    fn from_args(args: &Args) -> Result<Self> {
+        let obj = args.as_object().context("Parameters should be an object")?;
+        if let Some(name) = obj
+            .keys()
+            .find(|name| Self::SPECS.iter().all(|spec| spec.name != *name))
+        {
+            bail!("Unknown parameter `{}`", name);
+        }
+
+        let mut full = Map::new();
+        for spec in Self::SPECS {
+            let v = obj
+                .get(spec.name)
+                .cloned()
+                .unwrap_or_else(|| spec.default_value());
+            spec.check(&v)
+                .map_err(|err| format_err!("Invalid parameter `{}`: {}", spec.name, err))?;
+            full.insert(spec.name.to_owned(), v);
+        }
+        let params: Self = serde_json::from_value(Value::Object(full))?;
+        params.check()?;
+        Ok(params)
+    }
+
+    fn to_args(&self) -> Args {
+        serde_json::to_value(self).expect("Parameters should be serializable")
+    }
+
+    fn defaults() -> Self {
+        Self::from_args(&Value::Object(Map::new())).expect("Invalid default parameters")
+    }
+}
+
+/// Receiver of the fraction of work done, in [0, 1].
+pub type ProgressSink = Arc<dyn Fn(f32) + Send + Sync>;
+
+/// Receiver of messages reported by processors, eg. statistics of the result.
+pub type LogSink = Arc<dyn Fn(&str) + Send + Sync>;
+
+/// Handle of a running job, shared between the runner and the processor.
+#[derive(Clone, Default)]
+pub struct Job {
+    cancelled: Arc<AtomicBool>,
+    progress: Option<ProgressSink>,
+    log: Option<LogSink>,
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Job")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+impl Job {
+    pub fn with_progress(sink: impl Fn(f32) + Send + Sync + 'static) -> Self {
+        Self {
+            cancelled: Default::default(),
+            progress: Some(Arc::new(sink)),
+            log: None,
+        }
+    }
+
+    /// Receive messages from processors. They are dropped by default.
+    pub fn with_log(mut self, sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.log = Some(Arc::new(sink));
+        self
+    }
+
+    /// The `idx`-th of `total` sequential parts of this job, eg. a step in a pipeline.
+    pub fn part(&self, idx: usize, total: usize) -> Self {
+        let progress = self.progress.clone().map(|sink| -> ProgressSink {
+            Arc::new(move |frac| sink((idx as f32 + frac) / total as f32))
+        });
+        Self {
+            cancelled: self.cancelled.clone(),
+            progress,
+            log: self.log.clone(),
+        }
+    }
+
+    pub fn report(&self, frac: f32) {
+        if let Some(sink) = &self.progress {
+            sink(frac);
+        }
+    }
+
+    pub fn log(&self, msg: &str) {
+        if let Some(sink) = &self.log {
+            sink(msg);
+        }
+    }
+
+    /// Track `total` units of work, eg. rows of the output.
+    pub fn progress(&self, total: usize) -> Progress<'_> {
+        self.report(0.0);
+        Progress {
+            job: self,
+            total: total.max(1),
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    /// Request the job to stop. Processors check it cooperatively and fail with `Cancelled`.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Cancelled.into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Counter of finished work units, reported to the job whenever the percentage changes.
+#[derive(Debug)]
+pub struct Progress<'a> {
+    job: &'a Job,
+    total: usize,
+    done: AtomicUsize,
+}
+
+impl Progress<'_> {
+    /// Mark a unit as finished. Can be called from multiple threads.
+    pub fn tick(&self) {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if done * 100 / self.total != (done - 1) * 100 / self.total {
+            self.job.report(done as f32 / self.total as f32);
+        }
+    }
+}
+
+/// Returned when a job is cancelled, rather than finished or failed.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Cancelled")
+    }
+}
+
+impl Fail for Cancelled {}
+
+pub fn is_cancelled(err: &failure::Error) -> bool {
+    err.downcast_ref::<Cancelled>().is_some()
+}
+
+/// A processor with typed parameters.
+pub trait Processor: Send + Sync + 'static {
+    /// Identifier used in command line and saved files.
+    const NAME: &'static str;
+    type Params: Params;
+
+    /// Long-running processors should check `job` for cancellation and report progress to it.
+    fn run(&self, params: &Self::Params, src: Image, job: &Job) -> Result<Image>;
+}
+
+/// Type-erased `Processor`, shared by the GUI and the command-line runner.
+pub trait ImageProcessor: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn specs(&self) -> &'static [ParamSpec];
+
+    /// Validate arguments and fill in defaults.
+    fn check_args(&self, args: &Args) -> Result<Args>;
+
+    fn run(&self, args: &Args, src: Image, job: &Job) -> Result<Image>;
+}
+
+impl<P: Processor> ImageProcessor for P {
+    fn name(&self) -> &'static str {
+        P::NAME
+    }
+
+    fn specs(&self) -> &'static [ParamSpec] {
+        P::Params::SPECS
+    }
+
+    fn check_args(&self, args: &Args) -> Result<Args> {
+        Ok(P::Params::from_args(args)?.to_args())
+    }
+
+    fn run(&self, args: &Args, src: Image, job: &Job) -> Result<Image> {
+        let params = P::Params::from_args(args)?;
+        Processor::run(self, &params, src, job)
+    }
+}
+
+pub fn load_processors() -> Vec<Arc<dyn ImageProcessor>> {
+    use crate::{
+        affine::AffineTransform,
+        dct::{JpegQuantize, DCT},
+        deconv::Deconv,
+        dft::DFT,
+        edge::Edge,
+        filter::Filter,
+        freq_filter::FreqFilter,
+        morphology::Morphology,
+        noise::Noise,
+    };
+    vec![
+        Arc::new(AffineTransform),
+        Arc::new(DFT),
+        Arc::new(Noise),
+        Arc::new(Filter),
+        Arc::new(FreqFilter),
+        Arc::new(Deconv),
+        Arc::new(DCT),
+        Arc::new(JpegQuantize),
+        Arc::new(Morphology),
+        Arc::new(Edge),
+    ]
+}
+
+pub fn find_processor(name: &str) -> Result<Arc<dyn ImageProcessor>> {
+    load_processors()
+        .into_iter()
+        .find(|pro| pro.name() == name)
+        .ok_or_else(|| format_err!("Unknown processor `{}`", name))
+}
+
+/// Format the time elapsed like `1.234 567 890 s`.
+pub fn format_duration(d: Duration) -> String {
+    let ns = d.as_nanos();
+    format!(
+        "{}.{:03} {:03} {:03} s",
+        ns / 1_000_000_000,
+        ns / 1_000_000 % 1_000,
+        ns / 1_000 % 1_000,
+        ns % 1_000,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::{FilterParams, FilterType};
+    use ndarray::Array;
+    use serde_json::json;
+
+    #[test]
+    fn test_params_from_args() {
+        let params = FilterParams::defaults();
+        assert_eq!(params.ty, FilterType::Box);
+        assert_eq!(params.neighbor, 7);
+
+        let params =
+            FilterParams::from_args(&json!({ "type": "bilateral", "sigma_r": 0.1 })).unwrap();
+        assert_eq!(params.ty, FilterType::Bilateral);
+        assert!((params.sigma_r - 0.1).abs() < 1e-6);
+        let args = params.to_args();
+        assert_eq!(args["neighbor"], json!(7));
+        assert_eq!(FilterParams::from_args(&args).unwrap().to_args(), args);
+
+        assert!(FilterParams::from_args(&json!({ "foo": 1 })).is_err());
+        assert!(FilterParams::from_args(&json!({ "type": "foo" })).is_err());
+        assert!(FilterParams::from_args(&json!({ "neighbor": 1000 })).is_err());
+        assert!(FilterParams::from_args(&json!({ "neighbor": 8 })).is_err());
+        assert!(FilterParams::from_args(&json!({ "sigma": "1" })).is_err());
+    }
+
+    #[test]
+    fn test_cancel() {
+        let img = Image::Normal(Array::zeros((16, 16, 3)));
+        let filter = find_processor("filter").unwrap();
+        let args = json!({ "type": "bilateral" });
+        assert!(filter.run(&args, img.clone(), &Job::default()).is_ok());
+
+        let job = Job::default();
+        job.cancel();
+        let err = filter.run(&args, img, &job).unwrap_err();
+        assert!(is_cancelled(&err));
+    }
+
+    #[test]
+    fn test_progress() {
+        use std::sync::Mutex;
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reports_ = reports.clone();
+        let job = Job::with_progress(move |frac| reports_.lock().unwrap().push(frac));
+        let img = Image::Normal(Array::zeros((64, 64, 3)));
+        find_processor("filter")
+            .unwrap()
+            .run(&json!({}), img, &job.part(1, 2))
+            .unwrap();
+
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports[0], 0.5);
+        assert_eq!(reports.iter().cloned().fold(0.0, f32::max), 1.0);
+    }
+}
@@ -0,0 +1,315 @@
+use crate::{
+    affine::{affine_trans, AffineParams},
+    dft::{fft_2d, fft_shift},
+    image::to_gray,
+    processor::Job,
+    Result,
+};
+use failure::ensure;
+use ndarray::prelude::*;
+use num_complex::Complex32 as C;
+use std::f32::consts::PI;
+
+/// Transform aligning the moving image to the reference, in terms of `AffineParams`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Registration {
+    pub scale: f32,
+    /// Rotation in degrees.
+    pub rotate: f32,
+    pub shift_x: f32,
+    pub shift_y: f32,
+    /// Peak of the normalized correlation in [0, 1]. Low values indicate a poor match.
+    pub response: f32,
+}
+
+impl Registration {
+    /// Parameters of `AffineTransform` resampling the moving image onto the reference.
+    pub fn to_affine_params(&self) -> AffineParams {
+        AffineParams {
+            scale: self.scale,
+            rotate: self.rotate,
+            shift_x: self.shift_x,
+            shift_y: self.shift_y,
+            // Aligned to the reference of the same size.
+            keep_size: true,
+            ocl: false,
+        }
+    }
+}
+
+/// Estimate the transform aligning `moving` to `reference` of the same size.
+/// Rotation and scale are estimated only if `rotation_scale`, by log-polar transform of spectra.
+pub fn register(
+    reference: &Array3<f32>,
+    moving: &Array3<f32>,
+    rotation_scale: bool,
+    job: &Job,
+) -> Result<Registration> {
+    let (reference, moving) = (to_gray(reference), to_gray(moving));
+    ensure!(
+        reference.dim() == moving.dim(),
+        "Images should have the same size",
+    );
+    if !rotation_scale {
+        let ((dx, dy), response) = phase_correlation(&reference, &moving, job)?;
+        return Ok(Registration {
+            scale: 1.0,
+            rotate: 0.0,
+            shift_x: -dx,
+            shift_y: -dy,
+            response,
+        });
+    }
+
+    let (scale, rotate) = estimate_rotation_scale(&reference, &moving, &job.part(0, 3))?;
+    // Spectra are symmetric, so the rotation is ambiguous by 180 degrees.
+    let mut best = Registration {
+        scale,
+        rotate: 0.0,
+        shift_x: 0.0,
+        shift_y: 0.0,
+        response: -1.0,
+    };
+    for (i, &rotate) in [rotate, rotate + PI].iter().enumerate() {
+        let job = job.part(1 + i, 3);
+        let (scale, rotate) = (1.0 / scale, -rotate);
+        let warped = affine_trans(
+            moving.clone().insert_axis(Axis(2)),
+            scale,
+            rotate,
+            (0.0, 0.0),
+            false,
+            &job.part(0, 2),
+        )?;
+        let warped = fit_center(&warped.index_axis(Axis(2), 0), reference.dim());
+        let ((dx, dy), response) = phase_correlation(&reference, &warped, &job.part(1, 2))?;
+        if best.response < response {
+            best = Registration {
+                scale,
+                rotate: wrap_degrees(rotate.to_degrees()),
+                shift_x: -dx,
+                shift_y: -dy,
+                response,
+            };
+        }
+    }
+    Ok(best)
+}
+
+/// Estimate the shift `d` with `moving(p) = reference(p - d)` in sub-pixel precision.
+/// Returns the shift and the peak of the normalized correlation.
+pub fn phase_correlation(
+    reference: &Array2<f32>,
+    moving: &Array2<f32>,
+    job: &Job,
+) -> Result<((f32, f32), f32)> {
+    ensure!(
+        reference.dim() == moving.dim(),
+        "Images should have the same size",
+    );
+    let (h, w) = reference.dim();
+    let window = hann_window(h, w);
+    let spectrum = |mat: &Array2<f32>, job: &Job| fft_2d((mat * &window).mapv(C::from), false, job);
+    let f1 = spectrum(reference, &job.part(0, 3))?;
+    let f2 = spectrum(moving, &job.part(1, 3))?;
+
+    // Normalized cross-power spectrum, whose inverse is an impulse at the shift.
+    let mut cross = f2 * &f1.mapv(|v| v.conj());
+    cross.mapv_inplace(|v| {
+        let norm = v.norm();
+        if norm > 0.0 {
+            v / norm
+        } else {
+            C::new(0.0, 0.0)
+        }
+    });
+    let corr = fft_2d(cross, true, &job.part(2, 3))?.mapv(|v| v.re);
+
+    let ((px, py), &peak) = corr
+        .indexed_iter()
+        .fold(
+            None,
+            |best: Option<((usize, usize), &f32)>, (idx, v)| match best {
+                Some((_, m)) if m >= v => best,
+                _ => Some((idx, v)),
+            },
+        )
+        .unwrap();
+    let at = |x: usize, y: usize, dx: isize, dy: isize| {
+        let x = (x as isize + dx).rem_euclid(h as isize) as usize;
+        let y = (y as isize + dy).rem_euclid(w as isize) as usize;
+        corr[[x, y]]
+    };
+    // Vertex of the parabola through the peak and its neighbors.
+    let refine = |l: f32, r: f32| {
+        let d = l - 2.0 * peak + r;
+        if d < 0.0 {
+            0.5 * (l - r) / d
+        } else {
+            0.0
+        }
+    };
+    let dx = px as f32 + refine(at(px, py, -1, 0), at(px, py, 1, 0));
+    let dy = py as f32 + refine(at(px, py, 0, -1), at(px, py, 0, 1));
+    // Shifts over the half are negative ones wrapped around.
+    let wrap = |d: f32, n: usize| if d > n as f32 / 2.0 { d - n as f32 } else { d };
+    // Inverse FFT is normalized by `1 / sqrt(h * w)`.
+    let response = peak / ((h * w) as f32).sqrt();
+    Ok(((wrap(dx, h), wrap(dy, w)), response))
+}
+
+/// Estimate scale and rotation in radians of `moving` relative to `reference`,
+/// from shifts of magnitude spectra in log-polar coordinates.
+fn estimate_rotation_scale(
+    reference: &Array2<f32>,
+    moving: &Array2<f32>,
+    job: &Job,
+) -> Result<(f32, f32)> {
+    let (h, w) = reference.dim();
+    // Use the center square, so that frequencies have the same unit on both axes.
+    let n = h.min(w);
+    let window = hann_window(n, n);
+    let log_polar_spectrum = |mat: &Array2<f32>, job: &Job| -> Result<Array2<f32>> {
+        let mat = fit_center(&mat.view(), (n, n)) * &window;
+        let spectrum = fft_shift(&fft_2d(mat.mapv(C::from), false, job)?, false);
+        // Emphasize high frequencies, which are less affected by the window.
+        let c = (n / 2) as f32;
+        let mag = Array::from_shape_fn((n, n), |(x, y)| {
+            let t = ((x as f32 - c) / n as f32 * PI).cos() * ((y as f32 - c) / n as f32 * PI).cos();
+            spectrum[[x, y]].norm() * (1.0 - t) * (2.0 - t)
+        });
+        Ok(log_polar(&mag))
+    };
+    let lp1 = log_polar_spectrum(reference, &job.part(0, 3))?;
+    let lp2 = log_polar_spectrum(moving, &job.part(1, 3))?;
+    let ((dphi, drho), _) = phase_correlation(&lp1, &lp2, &job.part(2, 3))?;
+
+    let (nphi, nrho) = lp1.dim();
+    let log_base = ((n / 2) as f32).ln() / nrho as f32;
+    // Spectra are rotated along with the image, but scaled inversely.
+    Ok(((-drho * log_base).exp(), dphi * PI / nphi as f32))
+}
+
+/// Resample a centered spectrum at (angle, log radius), with angles in [0, pi).
+fn log_polar(mat: &Array2<f32>) -> Array2<f32> {
+    let (h, w) = mat.dim();
+    let (cx, cy) = ((h / 2) as f32, (w / 2) as f32);
+    let log_base = cx.min(cy).ln() / w as f32;
+    Array::from_shape_fn((h, w), |(i, j)| {
+        let phi = i as f32 * PI / h as f32;
+        let r = (j as f32 * log_base).exp();
+        bilinear(mat, cx + r * phi.cos(), cy + r * phi.sin())
+    })
+}
+
+/// Sample at a fractional position. Pixels out of the image are treated as zero.
+fn bilinear(mat: &Array2<f32>, x: f32, y: f32) -> f32 {
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+    let at = |dx: isize, dy: isize| {
+        let (x, y) = (x0 as isize + dx, y0 as isize + dy);
+        if 0 <= x && 0 <= y {
+            mat.get((x as usize, y as usize)).copied().unwrap_or(0.0)
+        } else {
+            0.0
+        }
+    };
+    (at(0, 0) * (1.0 - fy) + at(0, 1) * fy) * (1.0 - fx)
+        + (at(1, 0) * (1.0 - fy) + at(1, 1) * fy) * fx
+}
+
+/// Crop or pad with zeros to `(h, w)`, keeping the center.
+fn fit_center(mat: &ArrayView2<f32>, (h, w): (usize, usize)) -> Array2<f32> {
+    let (h1, w1) = mat.dim();
+    let (ox, oy) = (
+        (h1 as isize - h as isize) / 2,
+        (w1 as isize - w as isize) / 2,
+    );
+    Array::from_shape_fn((h, w), |(x, y)| {
+        let (x, y) = (x as isize + ox, y as isize + oy);
+        if 0 <= x && 0 <= y {
+            mat.get((x as usize, y as usize)).copied().unwrap_or(0.0)
+        } else {
+            0.0
+        }
+    })
+}
+
+/// Taper borders to reduce artifacts of the periodic extension of FFT.
+fn hann_window(h: usize, w: usize) -> Array2<f32> {
+    let hann = |i: usize, n: usize| 0.5 - 0.5 * (2.0 * PI * (i as f32 + 0.5) / n as f32).cos();
+    Array::from_shape_fn((h, w), |(x, y)| hann(x, h) * hann(y, w))
+}
+
+/// Wrap into (-180, 180].
+fn wrap_degrees(deg: f32) -> f32 {
+    let deg = deg.rem_euclid(360.0);
+    if deg > 180.0 {
+        deg - 360.0
+    } else {
+        deg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{affine::AffineTransform, image::Image, processor::Processor};
+
+    /// Smooth blobs, sampled with an offset.
+    fn blobs(h: usize, w: usize, (dx, dy): (f32, f32)) -> Array2<f32> {
+        const BLOBS: [(f32, f32, f32); 5] = [
+            (20.0, 30.0, 4.0),
+            (40.0, 25.0, 6.0),
+            (30.0, 55.0, 3.0),
+            (50.0, 50.0, 5.0),
+            (25.0, 45.0, 2.0),
+        ];
+        Array::from_shape_fn((h, w), |(x, y)| {
+            let (x, y) = (x as f32 - dx, y as f32 - dy);
+            BLOBS
+                .iter()
+                .map(|&(cx, cy, r)| (-((x - cx).powi(2) + (y - cy).powi(2)) / (2.0 * r * r)).exp())
+                .sum()
+        })
+    }
+
+    #[test]
+    fn test_register() {
+        let job = Job::default();
+        let reference = blobs(72, 80, (0.0, 0.0));
+        let moving = blobs(72, 80, (3.4, -5.7));
+        let ((dx, dy), response) = phase_correlation(&reference, &moving, &job).unwrap();
+        assert!((dx - 3.4).abs() < 0.2, "{}", dx);
+        assert!((dy + 5.7).abs() < 0.2, "{}", dy);
+        assert!(response > 0.3);
+
+        // Sharp edges give features at high frequencies.
+        let reference = Array::from_shape_fn((72, 80, 1), |(x, y, _)| {
+            let (x, y) = (x as f32 - 36.0, y as f32 - 40.0);
+            let rect = |x0: f32, y0: f32, x1: f32, y1: f32| {
+                (x0 <= x && x < x1 && y0 <= y && y < y1) as u8 as f32
+            };
+            rect(-20.0, -15.0, 5.0, -5.0)
+                + rect(-8.0, 0.0, 12.0, 22.0) * 0.6
+                + rect(8.0, -20.0, 14.0, 10.0) * 0.3
+        });
+        let moving = affine_trans(reference.clone(), 1.2, 0.5, (0.0, 0.0), false, &job).unwrap();
+        let moving = fit_center(&moving.index_axis(Axis(2), 0), (72, 80)).insert_axis(Axis(2));
+        let reg = register(&reference, &moving, true, &job).unwrap();
+        assert!((reg.scale - 1.0 / 1.2).abs() < 0.03, "{:?}", reg);
+        assert!((reg.rotate + 0.5f32.to_degrees()).abs() < 2.0, "{:?}", reg);
+        assert!(reg.shift_x.abs() < 1.0 && reg.shift_y.abs() < 1.0);
+
+        // The aligned image is in the size of the reference.
+        let params = reg.to_affine_params();
+        let aligned = AffineTransform
+            .run(&params, Image::Normal(moving), &job)
+            .unwrap()
+            .expect_normal()
+            .unwrap();
+        assert_eq!(aligned.dim(), reference.dim());
+        let diff = (&aligned - &reference).mapv(f32::abs).mean().unwrap();
+        assert!(diff < 0.05, "{}", diff);
+    }
+}
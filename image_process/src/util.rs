@@ -0,0 +1,14 @@
+use failure::{format_err, Error};
+use std::fmt::Display;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+pub trait OptionExt<T> {
+    fn context(self, context: impl Display + Send + Sync + 'static) -> Result<T>;
+}
+
+impl<T> OptionExt<T> for Option<T> {
+    fn context(self, context: impl Display + Send + Sync + 'static) -> Result<T> {
+        self.ok_or_else(|| format_err!("{}", context))
+    }
+}